@@ -0,0 +1,194 @@
+// CPU bus
+// Everything the cpu reads or writes goes through here
+// Decodes the address and routes it to the internal RAM, PPU, APU/IO or the cartridge
+// Also clocks the PPU, which shares the cartridge with the cpu
+
+use mapper::Mapper;
+use ppu::Ppu;
+use util::xorshift;
+
+// Power-up contents of the internal RAM
+// Real consoles come up with a mostly random pattern, some games read it before initialising
+#[derive(Clone, Copy, PartialEq)]
+pub enum RamInit {
+    Zeros,
+    Ones, // every byte $FF
+    Random(u64), // pseudo random, from the given seed
+}
+
+pub struct Bus {
+    // 2kB of internal RAM
+    ram: [u8; 0x800],
+    ram_init: RamInit, // power-up pattern of the internal RAM
+
+    // PPU, its registers sit at $2000 - $2007
+    ppu: Ppu,
+
+    // APU and I/O registers $4000 - $4017, likewise only latched for now
+    io_registers: [u8; 0x18],
+
+    // Page written to $4014, copied to OAM once the cpu lets go of the bus
+    oam_dma: Option<u8>,
+
+    // Last value driven onto the data bus, read back from addresses nothing responds to
+    open_bus: u8,
+
+    // Board logic of the inserted cartridge
+    mapper: Option<Box<dyn Mapper>>,
+}
+
+impl Bus {
+    pub fn new() -> Bus {
+        Bus {
+            ram: [0u8; 0x800],
+            ram_init: RamInit::Zeros,
+            ppu: Ppu::new(),
+            io_registers: [0u8; 0x18],
+            oam_dma: None,
+            open_bus: 0,
+            mapper: None,
+        }
+    }
+
+    // Sets the pattern the internal RAM is filled with at power on
+    pub fn set_ram_init(&mut self, ram_init:RamInit) {
+        self.ram_init = ram_init;
+    }
+
+    // Power on
+    // Fills the internal RAM with its power-up pattern
+    pub fn power_on(&mut self) {
+        let mut seed = match self.ram_init { RamInit::Random(seed) => seed, _ => 0 };
+        for byte in self.ram.iter_mut() {
+            *byte = match self.ram_init {
+                RamInit::Zeros => 0x00,
+                RamInit::Ones => 0xFF,
+                RamInit::Random(_) => xorshift(&mut seed) as u8,
+            };
+        }
+        self.ppu.power_on();
+    }
+
+    // Reset button, the PPU is the only other chip on the reset line that cares
+    pub fn reset(&mut self) {
+        self.ppu.reset();
+    }
+
+    // Plugs a cartridge into the console
+    pub fn insert_cartridge(&mut self, mapper:Box<dyn Mapper>) {
+        self.mapper = Some(mapper);
+    }
+
+    pub fn has_cartridge(&self) -> bool {
+        self.mapper.is_some()
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    // Level of the NMI line, driven by the PPU at the start of vblank
+    pub fn nmi(&self) -> bool {
+        self.ppu.nmi()
+    }
+
+    // Level of the IRQ line, the cartridge being the only source so far
+    pub fn irq(&self) -> bool {
+        self.mapper.as_ref().is_some_and(|mapper| mapper.irq())
+    }
+
+    // PRG-RAM on the cartridge, for battery saves
+    pub fn prg_ram(&mut self) -> &mut [u8] {
+        match self.mapper {
+            Some(ref mut mapper) => mapper.prg_ram(),
+            None => &mut [],
+        }
+    }
+
+    // Runs the PPU and the cartridge for the cpu cycles that have passed, three PPU dots to each cycle
+    // The PPU only runs with a cartridge in, as there are no pattern tables without one
+    pub fn clock(&mut self, cycles:u32) {
+        if let Some(ref mut mapper) = self.mapper {
+            for _ in 0..cycles * 3 {
+                self.ppu.step(mapper.as_mut());
+            }
+            mapper.clock(cycles);
+        }
+    }
+
+    // Takes the OAM DMA request left by a write to $4014, if there is one
+    pub fn take_oam_dma(&mut self) -> Option<u8> {
+        self.oam_dma.take()
+    }
+
+    // OAM DMA
+    // Copies the 256 byte page at $XX00 to OAM, a read from the page then a write to $2004 for every byte
+    // Only the copy happens here, the cpu is responsible for the cycles it is halted for
+    // There is no APU yet, so no DMC sample fetches to steal cycles in the middle of the copy
+    pub fn oam_dma(&mut self, page:u8) {
+        for i in 0..0x100 {
+            let val = self.read(((page as u16) << 8) | i);
+            self.write(0x2004, val);
+        }
+    }
+
+    // Reads a byte, with any side effects the read has on the hardware
+    pub fn read(&mut self, addr:u16) -> u8 {
+        if let Some(ref mut mapper) = self.mapper { mapper.cpu_access(false); }
+        let val = match addr {
+            0x2000..=0x3FFF => match self.mapper {
+                Some(ref mut mapper) => Some(self.ppu.read_register(addr, mapper.as_mut())),
+                None => None,
+            },
+            0x4020..=0xFFFF => self.mapper.as_mut().and_then(|mapper| mapper.cpu_read(addr)),
+            _ => self.read_internal(addr),
+        };
+        self.open_bus = val.unwrap_or(self.open_bus);
+        self.open_bus
+    }
+
+    // Reads a byte without side effects, for debugging and tracing
+    pub fn peek(&self, addr:u16) -> u8 {
+        let val = match addr {
+            0x4020..=0xFFFF => self.mapper.as_ref().and_then(|mapper| mapper.cpu_peek(addr)),
+            _ => self.read_internal(addr),
+        };
+        val.unwrap_or(self.open_bus)
+    }
+
+    pub fn write(&mut self, addr:u16, val:u8) {
+        self.open_bus = val;
+        if let Some(ref mut mapper) = self.mapper { mapper.cpu_access(true); }
+        match addr {
+            // 2kB of RAM mirrored four times
+            0x0000..=0x1FFF => self.ram[addr as usize & 0x07FF] = val,
+            // 8 PPU registers repeated every 8 bytes
+            0x2000..=0x3FFF => if let Some(ref mut mapper) = self.mapper {
+                self.ppu.write_register(addr, val, mapper.as_mut());
+                mapper.cpu_write(addr, val);
+            },
+            0x4014 => self.oam_dma = Some(val),
+            0x4000..=0x4017 => self.io_registers[addr as usize - 0x4000] = val,
+            // APU test registers, disabled on retail consoles
+            0x4018..=0x401F => {},
+            _ => if let Some(ref mut mapper) = self.mapper { mapper.cpu_write(addr, val); },
+        }
+    }
+
+    // Console side of the address map, None for unmapped addresses
+    fn read_internal(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x0000..=0x1FFF => Some(self.ram[addr as usize & 0x07FF]),
+            0x2000..=0x3FFF => Some(self.ppu.peek_register(addr)),
+            // the APU registers and $4014 are write only, only the status and controller ports answer reads
+            0x4015..=0x4017 => Some(self.io_registers[addr as usize - 0x4000]),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Bus {
+        Bus::new()
+    }
+}
@@ -0,0 +1,364 @@
+// Cartridge
+// Loads an iNES or NES 2.0 ROM image from disk
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+
+use mapper::SUPPORTED_MAPPERS;
+use util::check_bit;
+
+// Size of the iNES header at the start of every ROM image
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_BANK_SIZE: usize = 0x4000; // 16kB
+const CHR_BANK_SIZE: usize = 0x2000; // 8kB
+
+// Reasons a ROM image can be rejected
+#[derive(Debug)]
+pub enum RomError {
+    Io(io::Error), // the file could not be opened or read
+    TruncatedHeader, // shorter than the 16 byte header
+    BadMagic, // does not start with "NES\x1A"
+    SizeMismatch { expected: usize, actual: usize }, // file length disagrees with the banks the header declares
+    NoPrgRom, // the header declares no PRG-ROM, so there is no code to run
+    UnsupportedMapper(u16),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RomError::Io(ref err) => write!(f, "could not read ROM: {}", err),
+            RomError::TruncatedHeader => write!(f, "file is too short to hold an iNES header"),
+            RomError::BadMagic => write!(f, "not an iNES file"),
+            RomError::SizeMismatch { expected, actual } => write!(f, "header declares {} bytes but the file has {}", expected, actual),
+            RomError::NoPrgRom => write!(f, "header declares no PRG-ROM"),
+            RomError::UnsupportedMapper(mapper) => write!(f, "mapper {} is not supported", mapper),
+        }
+    }
+}
+
+impl Error for RomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RomError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(err: io::Error) -> RomError {
+        RomError::Io(err)
+    }
+}
+
+// Nametable arrangement hard-wired on the cartridge board
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mirroring {
+    Horizontal, // $2000 = $2400, $2800 = $2C00 (vertical scrolling games)
+    Vertical, // $2000 = $2800, $2400 = $2C00 (horizontal scrolling games)
+    SingleScreenLower, // every nametable shows the first 1kB of VRAM
+    SingleScreenUpper, // every nametable shows the second 1kB of VRAM
+    FourScreen, // extra VRAM on the cartridge gives every nametable its own memory
+    Mapped([u8; 4]), // VRAM page (0 or 1) picked by the board for each of the four nametables
+}
+
+// CPU/PPU timing the cartridge was made for
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Timing {
+    Ntsc, // RP2C02
+    Pal, // RP2C07
+    MultiRegion, // runs on either
+    Dendy, // UMC 6527P famiclone
+}
+
+// Header flavour the image was stored with
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum HeaderFormat {
+    INes,
+    Nes2,
+}
+
+// A parsed ROM image
+// RAM sizes are in bytes, split into volatile and battery backed (NV) parts
+pub struct Cartridge {
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    pub submapper: u8, // always 0 for iNES headers
+    pub mirroring: Mirroring,
+    pub four_screen: bool, // the board carries its own VRAM for all four nametables
+    pub battery: bool, // the cartridge has battery backed memory that should be saved
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub vs_unisystem: bool,
+    pub playchoice_10: bool,
+    pub vs_ppu_type: u8, // NES 2.0 byte 13 for Vs. System games
+    pub vs_hardware_type: u8,
+    pub timing: Timing,
+    pub expansion_device: u8, // NES 2.0 default expansion device, 0 = unspecified
+
+    pub trainer: Option<Vec<u8>>, // 512 bytes destined for $7000
+    pub prg_rom: Vec<u8>,
+    pub chr_rom: Vec<u8>, // empty when the board uses CHR-RAM
+}
+
+impl Cartridge {
+    pub fn prg_rom_size(&self) -> usize {
+        self.prg_rom.len()
+    }
+
+    pub fn chr_rom_size(&self) -> usize {
+        self.chr_rom.len()
+    }
+}
+
+// Reads and parses the ROM at filepath
+pub fn load_rom(filepath:&str) -> Result<Cartridge, RomError> {
+    let mut file = File::open(filepath)?;
+    let mut buffer = Vec::new();
+    file.read_to_end(&mut buffer)?;
+    parse_rom(&buffer)
+}
+
+// Parses an iNES or NES 2.0 image already in memory
+// Layout: 16 byte header, optional 512 byte trainer, PRG-ROM, then CHR-ROM
+pub fn parse_rom(buffer:&[u8]) -> Result<Cartridge, RomError> {
+    // check header
+    if buffer.len() < HEADER_SIZE { return Err(RomError::TruncatedHeader); }
+    if b"NES\x1A" != &buffer[0..4] { return Err(RomError::BadMagic); }
+
+    let mut header = [0u8; HEADER_SIZE];
+    header.copy_from_slice(&buffer[..HEADER_SIZE]);
+
+    // NES 2.0 marks itself with %10 in bits 2-3 of flags 7
+    let format = if header[7] & 0x0C == 0x08 { HeaderFormat::Nes2 } else { HeaderFormat::INes };
+
+    // old dumping tools wrote their name over bytes 7-15, which then reads as garbage mapper bits,
+    // RAM size and region, so the whole lot is taken as zero (8kB of RAM, NTSC)
+    if format == HeaderFormat::INes && header[12..16].iter().any(|&b| b != 0) {
+        for byte in header[7..].iter_mut() { *byte = 0; }
+    }
+    let flags6 = header[6];
+    let flags7 = header[7];
+
+    // mapper number is split across the upper nibbles of flags 6 and 7
+    let mut mapper = (flags7 & 0xF0) as u16 | (flags6 >> 4) as u16;
+
+    let has_trainer = check_bit(flags6, 2);
+    let battery = check_bit(flags6, 1);
+    let console_type = flags7 & 0x03;
+
+    let mut cart = Cartridge {
+        format,
+        mapper: 0,
+        submapper: 0,
+        mirroring: if check_bit(flags6, 0) { Mirroring::Vertical } else { Mirroring::Horizontal },
+        four_screen: check_bit(flags6, 3),
+        battery,
+        prg_ram_size: 0,
+        prg_nvram_size: 0,
+        chr_ram_size: 0,
+        chr_nvram_size: 0,
+        vs_unisystem: console_type == 1,
+        playchoice_10: console_type == 2,
+        vs_ppu_type: 0,
+        vs_hardware_type: 0,
+        timing: Timing::Ntsc,
+        expansion_device: 0,
+        trainer: None,
+        prg_rom: Vec::new(),
+        chr_rom: Vec::new(),
+    };
+
+    let (prg_size, chr_size) = match format {
+        HeaderFormat::INes => {
+            // counted in 8kB units, with 0 meaning a single bank for compatibility
+            let ram_size = header[8].max(1) as usize * 0x2000;
+            if battery { cart.prg_nvram_size = ram_size; } else { cart.prg_ram_size = ram_size; }
+            if header[5] == 0 { cart.chr_ram_size = CHR_BANK_SIZE; }
+            if check_bit(header[9], 0) { cart.timing = Timing::Pal; }
+            (header[4] as usize * PRG_BANK_SIZE, header[5] as usize * CHR_BANK_SIZE)
+        },
+        HeaderFormat::Nes2 => {
+            mapper |= ((header[8] & 0x0F) as u16) << 8;
+            cart.submapper = header[8] >> 4;
+            cart.prg_ram_size = shift_size(header[10] & 0x0F);
+            cart.prg_nvram_size = shift_size(header[10] >> 4);
+            cart.chr_ram_size = shift_size(header[11] & 0x0F);
+            cart.chr_nvram_size = shift_size(header[11] >> 4);
+            cart.timing = match header[12] & 0x03 {
+                0 => Timing::Ntsc,
+                1 => Timing::Pal,
+                2 => Timing::MultiRegion,
+                _ => Timing::Dendy,
+            };
+            if cart.vs_unisystem {
+                cart.vs_ppu_type = header[13] & 0x0F;
+                cart.vs_hardware_type = header[13] >> 4;
+            }
+            cart.expansion_device = header[15] & 0x3F;
+            (rom_size(header[4], header[9] & 0x0F, PRG_BANK_SIZE), rom_size(header[5], header[9] >> 4, CHR_BANK_SIZE))
+        },
+    };
+    cart.mapper = mapper;
+
+    if prg_size == 0 { return Err(RomError::NoPrgRom); }
+    let trainer_size = if has_trainer { TRAINER_SIZE } else { 0 };
+    let expected = HEADER_SIZE.saturating_add(trainer_size).saturating_add(prg_size).saturating_add(chr_size);
+    if buffer.len() < expected {
+        return Err(RomError::SizeMismatch { expected, actual: buffer.len() });
+    }
+
+    if !SUPPORTED_MAPPERS.contains(&mapper) { return Err(RomError::UnsupportedMapper(mapper)); }
+
+    let prg_start = HEADER_SIZE + trainer_size;
+    let chr_start = prg_start + prg_size;
+    if has_trainer { cart.trainer = Some(buffer[HEADER_SIZE..prg_start].to_vec()); }
+    cart.prg_rom = buffer[prg_start..chr_start].to_vec();
+    cart.chr_rom = buffer[chr_start..chr_start + chr_size].to_vec();
+    Ok(cart)
+}
+
+// NES 2.0 ROM size from its LSB and MSB nibble
+// An MSB nibble of $F switches to exponent-multiplier notation, LSB = %EEEEEEMM and size = 2^E * (MM*2+1) bytes
+fn rom_size(lsb:u8, msb:u8, unit:usize) -> usize {
+    if msb == 0x0F {
+        let multiplier = (lsb & 0x03) as usize * 2 + 1;
+        1usize.checked_shl((lsb >> 2) as u32)
+            .and_then(|size| size.checked_mul(multiplier))
+            .unwrap_or(usize::MAX)
+    }
+    else {
+        (((msb as usize) << 8) | lsb as usize) * unit
+    }
+}
+
+// NES 2.0 RAM sizes are stored as a shift count, 64 << n bytes or nothing at all for 0
+fn shift_size(shift:u8) -> usize {
+    if shift == 0 { 0 } else { 64 << shift }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Header with the given bytes 4 - 15 followed by whatever banks it declares, filled with the bank number
+    fn image(header:&[u8], prg_banks:usize, chr_banks:usize) -> Vec<u8> {
+        let mut buffer = b"NES\x1A".to_vec();
+        buffer.extend_from_slice(header);
+        buffer.resize(HEADER_SIZE, 0);
+        if check_bit(buffer[6], 2) {
+            buffer.extend(vec![0xEE; TRAINER_SIZE]);
+        }
+        for bank in 0..prg_banks { buffer.extend(vec![bank as u8; PRG_BANK_SIZE]); }
+        for bank in 0..chr_banks { buffer.extend(vec![0x80 | bank as u8; CHR_BANK_SIZE]); }
+        buffer
+    }
+
+    #[test]
+    fn parses_ines_header() {
+        let cart = parse_rom(&image(&[2, 1, 0x43, 0x00], 2, 1)).unwrap();
+        assert_eq!(cart.format, HeaderFormat::INes);
+        assert_eq!(cart.mapper, 4);
+        assert_eq!(cart.mirroring, Mirroring::Vertical);
+        assert!(cart.battery);
+        assert_eq!(cart.prg_nvram_size, 0x2000);
+        assert_eq!(cart.prg_ram_size, 0);
+        assert_eq!(cart.prg_rom_size(), 2 * PRG_BANK_SIZE);
+        assert_eq!(cart.chr_rom_size(), CHR_BANK_SIZE);
+        assert_eq!(cart.prg_rom[PRG_BANK_SIZE], 1);
+        assert_eq!(cart.chr_rom[0], 0x80);
+        assert_eq!(cart.timing, Timing::Ntsc);
+    }
+
+    #[test]
+    fn ines_without_chr_rom_gets_chr_ram() {
+        let cart = parse_rom(&image(&[1, 0, 0x00, 0x00], 1, 0)).unwrap();
+        assert!(cart.chr_rom.is_empty());
+        assert_eq!(cart.chr_ram_size, CHR_BANK_SIZE);
+    }
+
+    #[test]
+    fn reads_trainer() {
+        let cart = parse_rom(&image(&[1, 1, 0x04, 0x00], 1, 1)).unwrap();
+        assert_eq!(cart.trainer.as_ref().map(|trainer| trainer.len()), Some(TRAINER_SIZE));
+        assert_eq!(cart.prg_rom[0], 0);
+    }
+
+    #[test]
+    fn assembles_mapper_from_both_nibbles() {
+        // 85 = $55, low nibble in flags 6 and high nibble in flags 7
+        let cart = parse_rom(&image(&[1, 1, 0x50, 0x50], 1, 1)).unwrap();
+        assert_eq!(cart.mapper, 85);
+    }
+
+    #[test]
+    fn ignores_flags7_under_diskdude_signature() {
+        // "DiskDude!" over bytes 7 - 15 would make the 'D' read as mapper $4X
+        let mut header = vec![1, 1, 0x40];
+        header.extend_from_slice(b"DiskDude!");
+        let cart = parse_rom(&image(&header, 1, 1)).unwrap();
+        assert_eq!(cart.mapper, 4);
+        // nor should 'k' and 'D' in bytes 8 and 9 read as 840kB of RAM and PAL
+        assert_eq!(cart.prg_ram_size, 0x2000);
+        assert_eq!(cart.timing, Timing::Ntsc);
+    }
+
+    #[test]
+    fn parses_nes2_header() {
+        // mapper 4 submapper 4, 8kB PRG-NVRAM, 8kB CHR-RAM, PAL
+        let cart = parse_rom(&image(&[1, 0, 0x42, 0x08, 0x40, 0x00, 0x70, 0x07, 0x01], 1, 0)).unwrap();
+        assert_eq!(cart.format, HeaderFormat::Nes2);
+        assert_eq!(cart.mapper, 4);
+        assert_eq!(cart.submapper, 4);
+        assert_eq!(cart.prg_ram_size, 0);
+        assert_eq!(cart.prg_nvram_size, 0x2000);
+        assert_eq!(cart.chr_ram_size, 0x2000);
+        assert_eq!(cart.timing, Timing::Pal);
+    }
+
+    #[test]
+    fn nes2_mapper_high_bits_come_from_byte_8() {
+        // mapper $155 is not supported, but the number still has to be assembled right
+        match parse_rom(&image(&[1, 1, 0x50, 0x58, 0x01], 1, 1)) {
+            Err(RomError::UnsupportedMapper(mapper)) => assert_eq!(mapper, 0x155),
+            _ => panic!("expected an unsupported mapper"),
+        }
+    }
+
+    #[test]
+    fn rom_size_in_units_and_exponent_notation() {
+        assert_eq!(rom_size(2, 0, PRG_BANK_SIZE), 2 * PRG_BANK_SIZE);
+        assert_eq!(rom_size(0x01, 0x01, CHR_BANK_SIZE), 0x101 * CHR_BANK_SIZE);
+        // 2^4 * (1*2+1)
+        assert_eq!(rom_size(0x11, 0x0F, PRG_BANK_SIZE), 48);
+        // 2^63 * 7 does not fit
+        assert_eq!(rom_size(0xFF, 0x0F, PRG_BANK_SIZE), usize::MAX);
+    }
+
+    #[test]
+    fn shift_size_zero_means_none() {
+        assert_eq!(shift_size(0), 0);
+        assert_eq!(shift_size(1), 128);
+        assert_eq!(shift_size(7), 0x2000);
+    }
+
+    #[test]
+    fn rejects_bad_images() {
+        assert!(matches!(parse_rom(b"NES\x1A"), Err(RomError::TruncatedHeader)));
+        assert!(matches!(parse_rom(&[0u8; 32]), Err(RomError::BadMagic)));
+        assert!(matches!(parse_rom(&image(&[0, 1, 0x00, 0x00], 0, 1)), Err(RomError::NoPrgRom)));
+        match parse_rom(&image(&[2, 1, 0x00, 0x00], 1, 1)) {
+            Err(RomError::SizeMismatch { expected, actual }) => {
+                assert_eq!(expected, HEADER_SIZE + 2 * PRG_BANK_SIZE + CHR_BANK_SIZE);
+                assert_eq!(actual, HEADER_SIZE + PRG_BANK_SIZE + CHR_BANK_SIZE);
+            },
+            _ => panic!("expected a size mismatch"),
+        }
+    }
+}
@@ -0,0 +1,576 @@
+// 6502 instruction handlers
+// Each opcode in the table points at one of these
+
+use util::{check_bit, two_u8_to_u16};
+use super::*;
+use super::opcodes::{AddressingMode, Operand};
+
+// Value the unstable ANE and LXA opcodes OR into the accumulator
+// Varies between chips (and with temperature), $EE is the most commonly observed
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+impl Cpu {
+    // Each handler receives the resolved operand of the current opcode
+
+    // ADC - ADd with Carry
+    // This instruction adds the contents of a memory location to the accumulator together with the carry bit. If overflow occurs the carry bit is set, this enables multiple byte addition to be performed.
+    pub(super) fn adc(&mut self, op:Operand) {
+        let val = self.read(op.addr);
+        self.add(val);
+    }
+
+    // AND - bitwise function
+    // performs bitwise AND with accumulator
+    pub(super) fn and(&mut self, op:Operand) {
+        self.a &= self.read(op.addr);
+        let a = self.a;
+        self.set_zn(a);
+    }
+
+    // ASL - Arithmic Shift Left
+    // This operation shifts all the bits of the accumulator or memory contents one bit left. Bit 0 is set to 0 and bit 7 is placed in the carry flag.
+    pub(super) fn asl(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| cpu.shift_left(val, false));
+    }
+
+    // Branches
+    // If the condition holds then move the program counter to the branch target.
+    pub(super) fn bcc(&mut self, op:Operand) { let cond = !check_bit(self.p, CARRY_FLAG); self.branch(op, cond); }
+    pub(super) fn bcs(&mut self, op:Operand) { let cond = check_bit(self.p, CARRY_FLAG); self.branch(op, cond); }
+    pub(super) fn beq(&mut self, op:Operand) { let cond = check_bit(self.p, ZERO_FLAG); self.branch(op, cond); }
+    pub(super) fn bne(&mut self, op:Operand) { let cond = !check_bit(self.p, ZERO_FLAG); self.branch(op, cond); }
+    pub(super) fn bmi(&mut self, op:Operand) { let cond = check_bit(self.p, NEGATIVE_FLAG); self.branch(op, cond); }
+    pub(super) fn bpl(&mut self, op:Operand) { let cond = !check_bit(self.p, NEGATIVE_FLAG); self.branch(op, cond); }
+    pub(super) fn bvc(&mut self, op:Operand) { let cond = !check_bit(self.p, OVERFLOW_FLAG); self.branch(op, cond); }
+    pub(super) fn bvs(&mut self, op:Operand) { let cond = check_bit(self.p, OVERFLOW_FLAG); self.branch(op, cond); }
+
+    // BIT - BIt Test
+    // The mask pattern in A is ANDed with the value in memory to set or clear the zero flag, but the result is not kept. Bits 7 and 6 of the value from memory are copied into the N and V flags.
+    pub(super) fn bit(&mut self, op:Operand) {
+        let val = self.read(op.addr);
+        self.set_bitflag(ZERO_FLAG, val & self.a == 0);
+        self.set_bitflag(OVERFLOW_FLAG, check_bit(val, 6));
+        self.set_bitflag(NEGATIVE_FLAG, check_bit(val, 7));
+    }
+
+    // BRK - Force Interrupt
+    // The program counter and processor status are pushed on the stack then the IRQ interrupt vector at $FFFE/F is loaded into the PC and the break flag in the status set to one.
+    pub(super) fn brk(&mut self, _op:Operand) {
+        self.pc = self.pc.wrapping_add(1); // padding byte
+        self.interrupt(Interrupt::Brk);
+    }
+
+    // Flag instructions
+    pub(super) fn clc(&mut self, _op:Operand) { self.set_bitflag(CARRY_FLAG, false); }
+    pub(super) fn sec(&mut self, _op:Operand) { self.set_bitflag(CARRY_FLAG, true); }
+    pub(super) fn cli(&mut self, _op:Operand) { self.set_bitflag(INTERRUPT_FLAG, false); }
+    pub(super) fn sei(&mut self, _op:Operand) { self.set_bitflag(INTERRUPT_FLAG, true); }
+    pub(super) fn clv(&mut self, _op:Operand) { self.set_bitflag(OVERFLOW_FLAG, false); }
+    pub(super) fn cld(&mut self, _op:Operand) { self.set_bitflag(DECIMAL_FLAG, false); }
+    pub(super) fn sed(&mut self, _op:Operand) { self.set_bitflag(DECIMAL_FLAG, true); }
+
+    // CMP / CPX / CPY - Compare Memory with a register
+    // Compares the contents of the register with another memory held value and sets the zero, carry and negative flags as appropriate.
+    pub(super) fn cmp(&mut self, op:Operand) { let a = self.a; let val = self.read(op.addr); self.compare(a, val); }
+    pub(super) fn cpx(&mut self, op:Operand) { let x = self.x; let val = self.read(op.addr); self.compare(x, val); }
+    pub(super) fn cpy(&mut self, op:Operand) { let y = self.y; let val = self.read(op.addr); self.compare(y, val); }
+
+    // DEC - Decrement Memory
+    // Subtracts one from the value held at a specified memory location setting the zero and negative flags as appropriate.
+    pub(super) fn dec(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| {
+            let res = val.wrapping_sub(1);
+            cpu.set_zn(res);
+            res
+        });
+    }
+
+    // DEX / DEY - DEcrement X / Y register
+    pub(super) fn dex(&mut self, _op:Operand) { self.x = self.x.wrapping_sub(1); let x = self.x; self.set_zn(x); }
+    pub(super) fn dey(&mut self, _op:Operand) { self.y = self.y.wrapping_sub(1); let y = self.y; self.set_zn(y); }
+
+    // EOR - exclusive OR bitwise function
+    // An exclusive OR is performed, bit by bit, on the accumulator contents using the contents of a byte of memory.
+    pub(super) fn eor(&mut self, op:Operand) {
+        self.a ^= self.read(op.addr);
+        let a = self.a;
+        self.set_zn(a);
+    }
+
+    // INC - INCriment Memory
+    // Adds one to the value held at a specified memory location setting the zero and negative flags as appropriate.
+    pub(super) fn inc(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| {
+            let res = val.wrapping_add(1);
+            cpu.set_zn(res);
+            res
+        });
+    }
+
+    // INX / INY - INCriment X / Y Register
+    pub(super) fn inx(&mut self, _op:Operand) { self.x = self.x.wrapping_add(1); let x = self.x; self.set_zn(x); }
+    pub(super) fn iny(&mut self, _op:Operand) { self.y = self.y.wrapping_add(1); let y = self.y; self.set_zn(y); }
+
+    // JMP - Jump
+    // Jumps to location in memory
+    pub(super) fn jmp(&mut self, op:Operand) {
+        self.pc = op.addr;
+    }
+
+    // JSR - Jump to Subroutine
+    // Pushes the address (minus one) of the return point on to the stack and then sets the program counter to the target memory address.
+    pub(super) fn jsr(&mut self, op:Operand) {
+        let ret = self.pc.wrapping_sub(1);
+        self.push_u16_to_stack(ret);
+        self.pc = op.addr;
+    }
+
+    // LDA / LDX / LDY - LoaD register
+    // Loads a byte of memory into the register setting the zero and negative flags as appropriate.
+    pub(super) fn lda(&mut self, op:Operand) { self.a = self.read(op.addr); let a = self.a; self.set_zn(a); }
+    pub(super) fn ldx(&mut self, op:Operand) { self.x = self.read(op.addr); let x = self.x; self.set_zn(x); }
+    pub(super) fn ldy(&mut self, op:Operand) { self.y = self.read(op.addr); let y = self.y; self.set_zn(y); }
+
+    // LSR - Logical Shift Right
+    // Each of the bits in A or M is shift one place to the right. The bit that was in bit 0 is shifted into the carry flag. Bit 7 is set to zero.
+    pub(super) fn lsr(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| cpu.shift_right(val, false));
+    }
+
+    // NOP - No OPeration
+    // Operand bytes (if any) are skipped
+    pub(super) fn nop(&mut self, _op:Operand) {}
+
+    // ORA - OR Accumulator
+    // Performs a bitwise OR with the Accumulator
+    pub(super) fn ora(&mut self, op:Operand) {
+        self.a |= self.read(op.addr);
+        let a = self.a;
+        self.set_zn(a);
+    }
+
+    // Stack instructions
+    pub(super) fn pha(&mut self, _op:Operand) { let acc = self.a; self.push_to_stack(acc); }
+    pub(super) fn php(&mut self, _op:Operand) { let status = self.p | STATUS_PUSH_MASK; self.push_to_stack(status); } // pushed with the break bit set
+    pub(super) fn pla(&mut self, _op:Operand) { self.a = self.pop_from_stack(); let acc = self.a; self.set_zn(acc); }
+    pub(super) fn plp(&mut self, _op:Operand) { self.pop_status(); }
+
+    // ROL - Rotate Left
+    // Move each of the bits in either A or M one place to the left. Bit 0 is filled with the current value of the carry flag whilst the old bit 7 becomes the new carry flag value.
+    pub(super) fn rol(&mut self, op:Operand) {
+        let carry = check_bit(self.p, CARRY_FLAG);
+        self.read_modify_write(op, |cpu, val| cpu.shift_left(val, carry));
+    }
+
+    // ROR - Rotate Right
+    // Move each of the bits in either A or M one place to the right. Bit 7 is filled with the current value of the carry flag whilst the old bit 0 becomes the new carry flag value.
+    pub(super) fn ror(&mut self, op:Operand) {
+        let carry = check_bit(self.p, CARRY_FLAG);
+        self.read_modify_write(op, |cpu, val| cpu.shift_right(val, carry));
+    }
+
+    // RTI - Return from Interupt
+    // The RTI instruction is used at the end of an interrupt processing routine. It pulls the processor flags from the stack followed by the program counter.
+    pub(super) fn rti(&mut self, _op:Operand) {
+        self.pop_status();
+        self.pc = self.pop_u16_from_stack();
+    }
+
+    // RTS - Return from Subroutine
+    // The RTS instruction is used at the end of a subroutine to return to the calling routine. It pulls the program counter (minus one) from the stack.
+    pub(super) fn rts(&mut self, _op:Operand) {
+        self.pc = self.pop_u16_from_stack().wrapping_add(1);
+    }
+
+    // SBC - Subtract with Carry
+    // This instruction subtracts the contents of a memory location to the accumulator together with the not of the carry bit. If overflow occurs the carry bit is clear, this enables multiple byte subtraction to be performed.
+    pub(super) fn sbc(&mut self, op:Operand) {
+        let val = self.read(op.addr);
+        self.subtract(val);
+    }
+
+    // STA / STX / STY - STore register
+    // Stores the register into Memory
+    pub(super) fn sta(&mut self, op:Operand) { let a = self.a; self.write(op.addr, a); }
+    pub(super) fn stx(&mut self, op:Operand) { let x = self.x; self.write(op.addr, x); }
+    pub(super) fn sty(&mut self, op:Operand) { let y = self.y; self.write(op.addr, y); }
+
+    // Register transfers
+    // Copies one register into another and sets the zero and negative flags as appropriate (except TXS).
+    pub(super) fn tax(&mut self, _op:Operand) { self.x = self.a; let x = self.x; self.set_zn(x); }
+    pub(super) fn tay(&mut self, _op:Operand) { self.y = self.a; let y = self.y; self.set_zn(y); }
+    pub(super) fn tsx(&mut self, _op:Operand) { self.x = self.sp; let x = self.x; self.set_zn(x); }
+    pub(super) fn txa(&mut self, _op:Operand) { self.a = self.x; let a = self.a; self.set_zn(a); }
+    pub(super) fn txs(&mut self, _op:Operand) { self.sp = self.x; }
+    pub(super) fn tya(&mut self, _op:Operand) { self.a = self.y; let a = self.a; self.set_zn(a); }
+
+    // Unofficial opcodes
+
+    // SLO - Shift Left OR accumulator
+    // This instruction shift left one bit in memory, then ORs the accumulator with the memory address and sets the negative, zero and carry flags as appropriate.
+    pub(super) fn slo(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| {
+            let res = cpu.shift_left(val, false);
+            cpu.a |= res;
+            let a = cpu.a;
+            cpu.set_zn(a);
+            res
+        });
+    }
+
+    // RLA - Rotate Left then AND accumulator
+    pub(super) fn rla(&mut self, op:Operand) {
+        let carry = check_bit(self.p, CARRY_FLAG);
+        self.read_modify_write(op, |cpu, val| {
+            let res = cpu.shift_left(val, carry);
+            cpu.a &= res;
+            let a = cpu.a;
+            cpu.set_zn(a);
+            res
+        });
+    }
+
+    // SRE - Shift Right then EOR accumulator
+    pub(super) fn sre(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| {
+            let res = cpu.shift_right(val, false);
+            cpu.a ^= res;
+            let a = cpu.a;
+            cpu.set_zn(a);
+            res
+        });
+    }
+
+    // RRA - Rotate Right then ADC
+    // The carry out of the rotate feeds the addition
+    pub(super) fn rra(&mut self, op:Operand) {
+        let carry = check_bit(self.p, CARRY_FLAG);
+        self.read_modify_write(op, |cpu, val| {
+            let res = cpu.shift_right(val, carry);
+            cpu.add(res);
+            res
+        });
+    }
+
+    // DCP - DEC then CMP
+    pub(super) fn dcp(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| {
+            let res = val.wrapping_sub(1);
+            let a = cpu.a;
+            cpu.compare(a, res);
+            res
+        });
+    }
+
+    // ISC - INC then SBC
+    pub(super) fn isc(&mut self, op:Operand) {
+        self.read_modify_write(op, |cpu, val| {
+            let res = val.wrapping_add(1);
+            cpu.subtract(res);
+            res
+        });
+    }
+
+    // SAX - Store A AND X
+    // Flags are left untouched
+    pub(super) fn sax(&mut self, op:Operand) {
+        let val = self.a & self.x;
+        self.write(op.addr, val);
+    }
+
+    // LAX - LDA and LDX at once
+    pub(super) fn lax(&mut self, op:Operand) {
+        let val = self.read(op.addr);
+        self.a = val;
+        self.x = val;
+        self.set_zn(val);
+    }
+
+    // ANC - AND then copy N into C
+    pub(super) fn anc(&mut self, op:Operand) {
+        self.and(op);
+        let negative = check_bit(self.p, NEGATIVE_FLAG);
+        self.set_bitflag(CARRY_FLAG, negative);
+    }
+
+    // ALR - AND then LSR A
+    pub(super) fn alr(&mut self, op:Operand) {
+        let val = self.a & self.read(op.addr);
+        self.a = self.shift_right(val, false);
+    }
+
+    // ARR - AND then ROR A
+    // Carry comes from bit 6 of the result and overflow from bit 6 XOR bit 5
+    pub(super) fn arr(&mut self, op:Operand) {
+        let val = self.a & self.read(op.addr);
+        let carry = check_bit(self.p, CARRY_FLAG);
+        let res = (val >> 1) | ((carry as u8) << 7);
+        self.a = res;
+        self.set_zn(res);
+        self.set_bitflag(CARRY_FLAG, check_bit(res, 6));
+        self.set_bitflag(OVERFLOW_FLAG, check_bit(res, 6) != check_bit(res, 5));
+    }
+
+    // AXS - X = (A AND X) - operand
+    // Carry is set as by CMP, the decimal flag and overflow are ignored
+    pub(super) fn axs(&mut self, op:Operand) {
+        let val = self.read(op.addr);
+        let ax = self.a & self.x;
+        self.x = ax.wrapping_sub(val);
+        self.set_bitflag(CARRY_FLAG, ax >= val);
+        let x = self.x;
+        self.set_zn(x);
+    }
+
+    // LAS - A, X and SP all become memory AND SP
+    pub(super) fn las(&mut self, op:Operand) {
+        let val = self.read(op.addr) & self.sp;
+        self.a = val;
+        self.x = val;
+        self.sp = val;
+        self.set_zn(val);
+    }
+
+    // ANE - A = (A OR magic) AND X AND operand
+    pub(super) fn ane(&mut self, op:Operand) {
+        let val = (self.a | UNSTABLE_MAGIC) & self.x & self.read(op.addr);
+        self.a = val;
+        self.set_zn(val);
+    }
+
+    // LXA - A = X = (A OR magic) AND operand
+    pub(super) fn lxa(&mut self, op:Operand) {
+        let val = (self.a | UNSTABLE_MAGIC) & self.read(op.addr);
+        self.a = val;
+        self.x = val;
+        self.set_zn(val);
+    }
+
+    // SHY / SHX / SHA - Store register AND (high byte of the base address + 1)
+    pub(super) fn shy(&mut self, op:Operand) { let (index, val) = (self.x, self.y); self.store_and_high(op, index, val); }
+    pub(super) fn shx(&mut self, op:Operand) { let (index, val) = (self.y, self.x); self.store_and_high(op, index, val); }
+    pub(super) fn sha(&mut self, op:Operand) { let (index, val) = (self.y, self.a & self.x); self.store_and_high(op, index, val); }
+
+    // TAS - SP = A AND X, then stored as by SHA
+    pub(super) fn tas(&mut self, op:Operand) {
+        self.sp = self.a & self.x;
+        let (index, val) = (self.y, self.sp);
+        self.store_and_high(op, index, val);
+    }
+
+    // HLT - Halt
+    // Locks up the cpu, only a reset recovers it
+    pub(super) fn hlt(&mut self, _op:Operand) {
+        self.halted = true;
+    }
+
+    // Instruction helpers
+
+    // BCD arithmetic only happens on a 6502 that has it, with the D flag set
+    fn decimal_mode(&self) -> bool {
+        self.variant == CpuVariant::Mos6502 && check_bit(self.p, DECIMAL_FLAG)
+    }
+
+    // ADC arithmetic
+    fn add(&mut self, val:u8) {
+        if self.decimal_mode() { self.add_decimal(val); }
+        else { self.add_with_carry(val); }
+    }
+
+    // SBC arithmetic
+    // subtraction is addition of the ones' complement, with carry acting as "not borrow"
+    fn subtract(&mut self, val:u8) {
+        if self.decimal_mode() {
+            // the NMOS 6502 sets every flag exactly as binary mode would, only A gets the BCD result
+            let (a, carry) = (self.a, check_bit(self.p, CARRY_FLAG));
+            self.add_with_carry(!val);
+            self.a = bcd_subtract(a, val, carry);
+        }
+        else { self.add_with_carry(!val); }
+    }
+
+    fn add_with_carry(&mut self, val:u8) {
+        let sum = self.a as u16 + val as u16 + check_bit(self.p, CARRY_FLAG) as u16;
+        let res = sum as u8;
+        self.set_bitflag(CARRY_FLAG, sum > 0xFF);
+        // overflow when both inputs share a sign that differs from the result's
+        self.set_bitflag(OVERFLOW_FLAG, (self.a ^ res) & (val ^ res) & 0x80 != 0);
+        self.a = res;
+        self.set_zn(res);
+    }
+
+    // NMOS 6502 decimal addition
+    // Z comes from the binary sum, while N and V are taken after the low digit is
+    // adjusted but before the high digit is, so they do not always match the BCD result
+    fn add_decimal(&mut self, val:u8) {
+        let (a, carry) = (self.a as u16, check_bit(self.p, CARRY_FLAG) as u16);
+        let val16 = val as u16;
+
+        let mut lo = (a & 0x0F) + (val16 & 0x0F) + carry;
+        let mut hi = (a >> 4) + (val16 >> 4);
+        if lo > 0x09 { lo += 0x06; }
+        if lo > 0x0F { hi += 1; }
+
+        let intermediate = ((hi << 4) | (lo & 0x0F)) as u8;
+        self.set_bitflag(ZERO_FLAG, (a + val16 + carry) as u8 == 0);
+        self.set_bitflag(NEGATIVE_FLAG, check_bit(intermediate, 7));
+        self.set_bitflag(OVERFLOW_FLAG, (self.a ^ intermediate) & !(self.a ^ val) & 0x80 != 0);
+
+        if hi > 0x09 { hi += 0x06; }
+        self.set_bitflag(CARRY_FLAG, hi > 0x0F);
+        self.a = ((hi << 4) | (lo & 0x0F)) as u8;
+    }
+
+    fn compare(&mut self, reg:u8, val:u8) {
+        self.set_bitflag(CARRY_FLAG, reg >= val);
+        self.set_zn(reg.wrapping_sub(val));
+    }
+
+    // a taken branch costs an extra cycle, and another if it lands in a different page
+    fn branch(&mut self, op:Operand, cond:bool) {
+        if cond {
+            self.cycles += if op.page_crossed { 2 } else { 1 };
+            self.pc = op.addr;
+        }
+    }
+
+    // Applies f to the accumulator or to the byte at the operand address, storing the result back
+    // Like the real cpu, memory is written twice: first the unmodified value, then the result
+    fn read_modify_write<F: FnOnce(&mut Cpu, u8) -> u8>(&mut self, op:Operand, f:F) {
+        if op.mode == AddressingMode::Accumulator {
+            let val = self.a;
+            self.a = f(self, val);
+        }
+        else {
+            let val = self.read(op.addr);
+            self.write(op.addr, val);
+            let res = f(self, val);
+            self.write(op.addr, res);
+        }
+    }
+
+    // Store used by SHY, SHX, SHA and TAS
+    // The value is ANDed with the high byte of the un-indexed address plus one, and when indexing
+    // crosses a page that same value replaces the high byte of the address written to
+    fn store_and_high(&mut self, op:Operand, index:u8, val:u8) {
+        let base = op.addr.wrapping_sub(index as u16);
+        let res = val & ((base >> 8) as u8).wrapping_add(1);
+        let addr = if op.page_crossed { two_u8_to_u16(res, op.addr as u8) } else { op.addr };
+        self.write(addr, res);
+    }
+
+    // shifts val left, filling bit 0 with carry_in and moving the old bit 7 into the carry flag
+    fn shift_left(&mut self, val:u8, carry_in:bool) -> u8 {
+        let res = (val << 1) | carry_in as u8;
+        self.set_bitflag(CARRY_FLAG, check_bit(val, 7));
+        self.set_zn(res);
+        res
+    }
+
+    // shifts val right, filling bit 7 with carry_in and moving the old bit 0 into the carry flag
+    fn shift_right(&mut self, val:u8, carry_in:bool) -> u8 {
+        let res = (val >> 1) | ((carry_in as u8) << 7);
+        self.set_bitflag(CARRY_FLAG, check_bit(val, 0));
+        self.set_zn(res);
+        res
+    }
+
+    // sets the zero and negative flags from a result
+    fn set_zn(&mut self, val:u8) {
+        self.set_bitflag(ZERO_FLAG, val == 0);
+        self.set_bitflag(NEGATIVE_FLAG, check_bit(val, 7));
+    }
+}
+
+// NMOS 6502 decimal subtraction, returning just the BCD result
+fn bcd_subtract(a:u8, val:u8, carry:bool) -> u8 {
+    let mut lo = (a & 0x0F) as i16 - (val & 0x0F) as i16 + carry as i16 - 1;
+    if lo < 0 { lo = ((lo - 0x06) & 0x0F) - 0x10; }
+    let mut res = (a & 0xF0) as i16 - (val & 0xF0) as i16 + lo;
+    if res < 0 { res -= 0x60; }
+    res as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Decimal mode 6502 with the given accumulator and carry
+    fn decimal_cpu(a:u8, carry:bool) -> Cpu {
+        let mut cpu = Cpu::new(CpuVariant::Mos6502);
+        cpu.a = a;
+        cpu.set_bitflag(DECIMAL_FLAG, true);
+        cpu.set_bitflag(CARRY_FLAG, carry);
+        cpu
+    }
+
+    fn flags(cpu:&Cpu) -> (bool, bool, bool, bool) {
+        (check_bit(cpu.p, NEGATIVE_FLAG), check_bit(cpu.p, OVERFLOW_FLAG), check_bit(cpu.p, ZERO_FLAG), check_bit(cpu.p, CARRY_FLAG))
+    }
+
+    #[test]
+    fn bcd_subtract_borrows_between_digits() {
+        assert_eq!(bcd_subtract(0x46, 0x12, true), 0x34);
+        assert_eq!(bcd_subtract(0x40, 0x13, true), 0x27);
+        assert_eq!(bcd_subtract(0x32, 0x02, false), 0x29);
+        assert_eq!(bcd_subtract(0x00, 0x01, true), 0x99);
+    }
+
+    #[test]
+    fn decimal_add_carries_between_digits() {
+        let mut cpu = decimal_cpu(0x09, false);
+        cpu.add(0x01);
+        assert_eq!(cpu.a, 0x10);
+        assert_eq!(flags(&cpu), (false, false, false, false));
+    }
+
+    #[test]
+    fn decimal_add_takes_n_before_the_high_digit_is_adjusted() {
+        // 99 + 01 = 00 carry 1, but the unadjusted high digit is $A so N is set
+        let mut cpu = decimal_cpu(0x99, false);
+        cpu.add(0x01);
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(flags(&cpu), (true, false, false, true));
+    }
+
+    #[test]
+    fn decimal_add_takes_z_from_the_binary_sum() {
+        // 50 + 50 = 00 carry 1, yet Z is clear as $50 + $50 = $A0
+        let mut cpu = decimal_cpu(0x50, false);
+        cpu.add(0x50);
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(flags(&cpu), (true, true, false, true));
+    }
+
+    #[test]
+    fn decimal_add_sets_v_like_a_signed_overflow() {
+        // 79 + 00 + carry = 80, overflowing from positive to negative
+        let mut cpu = decimal_cpu(0x79, true);
+        cpu.add(0x00);
+        assert_eq!(cpu.a, 0x80);
+        assert_eq!(flags(&cpu), (true, true, false, false));
+    }
+
+    #[test]
+    fn decimal_subtract_sets_flags_as_binary() {
+        // 00 - 01 = 99 with a borrow, N and Z come from the binary $FF
+        let mut cpu = decimal_cpu(0x00, true);
+        cpu.subtract(0x01);
+        assert_eq!(cpu.a, 0x99);
+        assert_eq!(flags(&cpu), (true, false, false, false));
+
+        // 01 - 01 = 00, Z set and no borrow
+        let mut cpu = decimal_cpu(0x01, true);
+        cpu.subtract(0x01);
+        assert_eq!(cpu.a, 0x00);
+        assert_eq!(flags(&cpu), (false, false, true, true));
+    }
+
+    #[test]
+    fn ricoh_ignores_decimal_flag() {
+        let mut cpu = decimal_cpu(0x09, false);
+        cpu.variant = CpuVariant::Ricoh2A03;
+        cpu.add(0x01);
+        assert_eq!(cpu.a, 0x0A);
+    }
+}
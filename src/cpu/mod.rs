@@ -0,0 +1,434 @@
+// 6502 CPU core
+// Executes instructions against the bus, services interrupts and counts cycles
+// Notes:
+// + three general purpose registers, a, x and y.
+// + little endian arch
+// + 148 instructions
+// + 8-bit stack pointer
+// + 16-bit program counter
+
+mod instructions;
+mod opcodes;
+
+use bus::Bus;
+use util::{check_bit, two_u8_to_u16};
+use self::opcodes::{AddressingMode, Operand, OPCODES};
+
+// Processor Flag bit positions
+const CARRY_FLAG: usize = 0;
+const ZERO_FLAG: usize = 1;
+const INTERRUPT_FLAG: usize = 2;
+const DECIMAL_FLAG: usize = 3;
+const BREAK_FLAG: usize = 4;
+const UNUSED_FLAG: usize = 5;
+const OVERFLOW_FLAG: usize = 6;
+const NEGATIVE_FLAG: usize = 7;
+
+// The break and unused bits are not real flags, they only take a value when P is pushed
+// In the p register the unused bit always reads as set and the break bit as clear
+const STATUS_PUSH_MASK: u8 = (1 << BREAK_FLAG) | (1 << UNUSED_FLAG);
+
+// Stack page, SP is an offset into it
+const STACK_PAGE: u16 = 0x0100;
+
+// Interrupt vectors
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+
+// Interrupt sources
+#[derive(Clone, Copy, PartialEq)]
+enum Interrupt {
+    Nmi, // non-maskable, edge triggered (vblank)
+    Irq, // maskable, level triggered (mappers, APU)
+    Brk, // software interrupt from the BRK instruction
+}
+
+
+// CPU variants
+// The NES uses Ricoh's 2A03, a 6502 with the decimal mode circuitry disconnected
+#[derive(Clone, Copy, PartialEq)]
+pub enum CpuVariant {
+    Ricoh2A03, // D flag can be set but ADC/SBC always work in binary
+    Mos6502, // NMOS 6502 with BCD arithmetic when D is set
+}
+
+
+pub struct Cpu {
+
+    // GPR (general purpose registers)
+    pub a: u8, // accumulator
+    pub x: u8, // index register
+    pub y: u8, // index register
+
+    // SP (stack pointer)
+    pub sp: u8,
+
+    // PC (program counter)
+    pub pc: u16,
+
+    // Processor Flags
+    pub p: u8, // Negative, oVerflow, (unused), (Break), Decimal, Interupt, Zero, Carry
+
+    // Everything the cpu can address
+    pub bus: Bus,
+
+    // Cycles elapsed since power on
+    cycles: u64,
+    clocked: u64, // cycles the rest of the system has been run for, trails cycles while an instruction executes
+
+    // Interrupt lines
+    nmi_line: bool, // current level of the NMI input (true = asserted)
+    nmi_pending: bool, // an NMI edge has been detected and not yet serviced
+    irq_line: bool, // current level of the IRQ input (true = asserted)
+    irq_inhibit: bool, // interrupt flag as seen by the last interrupt poll
+
+    // Set by the HLT opcodes, the cpu stops fetching until reset
+    halted: bool,
+
+    // Which 6502 this core behaves as
+    variant: CpuVariant,
+}
+
+impl Cpu {
+    // initializes registers
+    pub fn new(variant: CpuVariant) -> Cpu {
+        Cpu {
+            a: 0x00,
+            x: 0x00,
+            y: 0x00,
+            sp: 0x00, // becomes $FD after the reset sequence
+            pc: 0x0000, // loaded from the reset vector by power_on/reset
+            p: 0x24,
+            bus: Bus::new(),
+            cycles: 0,
+            clocked: 0,
+            nmi_line: false,
+            nmi_pending: false,
+            irq_line: false,
+            irq_inhibit: true,
+            halted: false,
+            variant,
+        }
+    }
+
+    // Power on
+    // Puts the cpu into its power-up state, fills the internal RAM and runs the reset sequence
+    pub fn power_on(&mut self) {
+        self.a = 0x00;
+        self.x = 0x00;
+        self.y = 0x00;
+        self.sp = 0x00;
+        self.p = 0x24;
+        self.cycles = 0;
+        self.clocked = 0;
+        self.nmi_line = false;
+        self.nmi_pending = false;
+        self.irq_line = false;
+
+        self.bus.power_on();
+
+        self.reset();
+    }
+
+    // Reset
+    // Runs the 7 cycle reset sequence, as when the console reset button is pressed
+    // This is the interrupt sequence with its stack writes suppressed, so SP drops by 3 and RAM is left untouched
+    pub fn reset(&mut self) {
+        self.sp = self.sp.wrapping_sub(3);
+        self.set_bitflag(INTERRUPT_FLAG, true);
+        self.irq_inhibit = true;
+        self.nmi_pending = false;
+        self.halted = false;
+        self.pc = self.read_u16(RESET_VECTOR);
+        self.cycles += 7;
+    }
+
+    // Tick function
+    // Reads an OPCODE, resolves its operand and executes it
+    // Returns the number of cycles the instruction took
+    // A pending interrupt is serviced in place of an instruction
+    // Afterwards the rest of the system is run for those cycles and the interrupt lines are sampled
+    pub fn tick(&mut self) -> u32 {
+        let start = self.cycles;
+        if self.halted {
+            // a locked up cpu ignores interrupts but the clock keeps running
+            self.cycles += 1;
+        }
+        else if self.nmi_pending {
+            self.nmi_pending = false;
+            self.cycles += 7;
+            self.interrupt(Interrupt::Nmi);
+        }
+        else if self.irq_line && !self.irq_inhibit {
+            self.cycles += 7;
+            self.interrupt(Interrupt::Irq);
+        }
+        else {
+            let inhibit_before = check_bit(self.p, INTERRUPT_FLAG);
+            let opcode = OPCODES[self.fetch() as usize];
+            let operand = self.resolve(opcode.mode);
+            self.cycles += opcode.cycles as u64;
+            if opcode.page_cross_penalty && operand.page_crossed {
+                self.cycles += 1;
+            }
+            (opcode.handler)(self, operand); // branches add their own penalties
+
+            // interrupts are polled before the last cycle, so CLI, SEI and PLP only affect the poll after the next instruction
+            self.irq_inhibit = match opcode.mnemonic {
+                "CLI" | "SEI" | "PLP" => inhibit_before,
+                _ => check_bit(self.p, INTERRUPT_FLAG),
+            };
+        }
+
+        // a write to $4014 halts the cpu while the bus copies a page to OAM
+        // one halt cycle, one more to line up when that lands on an odd cycle, then 256 reads and writes: 513 or 514 in all
+        if let Some(page) = self.bus.take_oam_dma() {
+            let alignment = self.cycles % 2;
+            self.bus.oam_dma(page);
+            self.cycles += 513 + alignment;
+        }
+
+        let now = self.cycles;
+        self.clock_bus_to(now);
+        (self.cycles - start) as u32
+    }
+
+    // Sets the level of the NMI line
+    // An NMI is triggered on the transition from released to asserted
+    pub fn set_nmi(&mut self, asserted:bool) {
+        if asserted && !self.nmi_line {
+            self.nmi_pending = true;
+        }
+        self.nmi_line = asserted;
+    }
+
+    // Sets the level of the IRQ line
+    // An IRQ is triggered for as long as the line is asserted and the interrupt flag is clear
+    pub fn set_irq(&mut self, asserted:bool) {
+        self.irq_line = asserted;
+    }
+
+    // Runs the rest of the system up to the given cycle and samples the interrupt lines
+    fn clock_bus_to(&mut self, cycle:u64) {
+        if cycle > self.clocked {
+            self.bus.clock((cycle - self.clocked) as u32);
+            self.clocked = cycle;
+        }
+        let nmi = self.bus.nmi();
+        self.set_nmi(nmi);
+        let irq = self.bus.irq();
+        self.set_irq(irq);
+    }
+
+    // Interrupt sequence
+    // Pushes the program counter and processor status, sets the interrupt flag and jumps through the vector
+    // Called once the 7 cycles of the sequence have been counted
+    fn interrupt(&mut self, kind:Interrupt) {
+        let pc = self.pc;
+        self.push_u16_to_stack(pc);
+
+        // the break flag only exists on the stack, and tells BRK apart from a hardware IRQ
+        let status = if kind == Interrupt::Brk { self.p | STATUS_PUSH_MASK } else { self.p | (1 << UNUSED_FLAG) };
+        self.push_to_stack(status);
+        self.set_bitflag(INTERRUPT_FLAG, true);
+        self.irq_inhibit = true;
+
+        // an NMI detected by the end of the fourth cycle, while PC is being pushed, hijacks a BRK or IRQ sequence
+        let pushed = self.cycles - 3;
+        self.clock_bus_to(pushed);
+        let vector = if kind == Interrupt::Nmi || self.nmi_pending {
+            self.nmi_pending = false;
+            NMI_VECTOR
+        }
+        else {
+            IRQ_VECTOR
+        };
+        self.pc = self.read_u16(vector);
+    }
+
+    // Cycles elapsed since power on
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Stopped by a HLT opcode, nothing more will run until reset
+    pub fn halted(&self) -> bool {
+        self.halted
+    }
+
+    // Reads a byte from the cpu address space
+    fn read(&mut self, addr:u16) -> u8 {
+        self.bus.read(addr)
+    }
+
+    // Writes a byte to the cpu address space
+    fn write(&mut self, addr:u16, val:u8) {
+        self.bus.write(addr, val);
+    }
+
+    // Reads a little endian word from the cpu address space
+    fn read_u16(&mut self, addr:u16) -> u16 {
+        two_u8_to_u16(self.read(addr.wrapping_add(1)), self.read(addr))
+    }
+
+    // Reads the byte at pc and moves pc past it
+    fn fetch(&mut self) -> u8 {
+        let val = self.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        val
+    }
+
+    // Reads the little endian word at pc and moves pc past it
+    fn fetch_u16(&mut self) -> u16 {
+        let lo = self.fetch();
+        let hi = self.fetch();
+        two_u8_to_u16(hi, lo)
+    }
+
+    // Addressing mode resolution
+    // Reads the operand bytes following the opcode (moving pc past them) and works out the effective address
+    fn resolve(&mut self, mode:AddressingMode) -> Operand {
+        let (addr, page_crossed) = match mode {
+            AddressingMode::Implied | AddressingMode::Accumulator => (0, false),
+            AddressingMode::Immediate => {
+                let addr = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                (addr, false)
+            },
+            AddressingMode::ZeroPage => (self.fetch() as u16, false),
+            // zero page indexing never leaves the zero page
+            AddressingMode::ZeroPageX => (self.fetch().wrapping_add(self.x) as u16, false),
+            AddressingMode::ZeroPageY => (self.fetch().wrapping_add(self.y) as u16, false),
+            // branch target, relative to the following instruction
+            AddressingMode::Relative => {
+                let offset = self.fetch() as i8;
+                let target = self.pc.wrapping_add(offset as u16);
+                (target, target & 0xFF00 != self.pc & 0xFF00)
+            },
+            AddressingMode::Absolute => (self.fetch_u16(), false),
+            AddressingMode::AbsoluteX => {
+                let base = self.fetch_u16();
+                let x = self.x;
+                self.indexed(base, x)
+            },
+            AddressingMode::AbsoluteY => {
+                let base = self.fetch_u16();
+                let y = self.y;
+                self.indexed(base, y)
+            },
+            // the 6502 does not carry into the high byte when the pointer sits on a page boundary
+            AddressingMode::Indirect => {
+                let ptr = self.fetch_u16();
+                let hi = self.read((ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF));
+                (two_u8_to_u16(hi, self.read(ptr)), false)
+            },
+            // pointer lives in the zero page, so both the index and the pointer high byte wrap within it
+            AddressingMode::IndirectX => {
+                let ptr = self.fetch().wrapping_add(self.x);
+                (self.read_zero_page_u16(ptr), false)
+            },
+            AddressingMode::IndirectY => {
+                let ptr = self.fetch();
+                let base = self.read_zero_page_u16(ptr);
+                let y = self.y;
+                self.indexed(base, y)
+            },
+        };
+        Operand { mode, addr, page_crossed }
+    }
+
+    // Adds an index register to a base address, reporting whether the carry crossed into the next page
+    // When it does the 6502 first reads from the un-carried address
+    fn indexed(&mut self, base:u16, index:u8) -> (u16, bool) {
+        let addr = base.wrapping_add(index as u16);
+        let page_crossed = base & 0xFF00 != addr & 0xFF00;
+        if page_crossed {
+            self.read((base & 0xFF00) | (addr & 0x00FF));
+        }
+        (addr, page_crossed)
+    }
+
+    // Reads a little endian pointer from the zero page, wrapping at $FF
+    fn read_zero_page_u16(&mut self, ptr:u8) -> u16 {
+        two_u8_to_u16(self.read(ptr.wrapping_add(1) as u16), self.read(ptr as u16))
+    }
+
+    // Disassembles the instruction at addr for tracing
+    // Uses peek so tracing never disturbs the hardware
+    pub fn disassemble(&self, addr:u16) -> String {
+        let opcode = OPCODES[self.bus.peek(addr) as usize];
+        let lo = self.bus.peek(addr.wrapping_add(1));
+        let hi = self.bus.peek(addr.wrapping_add(2));
+        let word = two_u8_to_u16(hi, lo);
+        let operand = match opcode.mode {
+            AddressingMode::Implied => String::new(),
+            AddressingMode::Accumulator => String::from(" A"),
+            AddressingMode::Immediate => format!(" #${:0>2x}", lo),
+            AddressingMode::ZeroPage => format!(" ${:0>2x}", lo),
+            AddressingMode::ZeroPageX => format!(" ${:0>2x},x", lo),
+            AddressingMode::ZeroPageY => format!(" ${:0>2x},y", lo),
+            AddressingMode::Relative => format!(" ${:0>4x}", addr.wrapping_add(opcode.bytes as u16).wrapping_add(lo as i8 as u16)),
+            AddressingMode::Absolute => format!(" ${:0>4x}", word),
+            AddressingMode::AbsoluteX => format!(" ${:0>4x},x", word),
+            AddressingMode::AbsoluteY => format!(" ${:0>4x},y", word),
+            AddressingMode::Indirect => format!(" (${:0>4x})", word),
+            AddressingMode::IndirectX => format!(" (${:0>2x},x)", lo),
+            AddressingMode::IndirectY => format!(" (${:0>2x}),y", lo),
+        };
+        format!("{}{}", opcode.mnemonic, operand)
+    }
+
+
+    // The stack lives in page $01 and grows downwards
+    // SP points at the next free slot, so a push writes then decrements and a pop increments then reads
+    fn push_to_stack(&mut self, v:u8){
+        let addr = STACK_PAGE | self.sp as u16;
+        self.write(addr, v);
+        self.sp = self.sp.wrapping_sub(1); //decriment stack pointer
+    }
+
+    fn pop_from_stack(&mut self) -> u8{
+        self.sp = self.sp.wrapping_add(1); //incriment stack pointer
+        self.read(STACK_PAGE | self.sp as u16)
+    }
+
+    // words are pushed high byte first, so they sit little endian in memory
+    fn push_u16_to_stack(&mut self, v:u16){
+        self.push_to_stack((v >> 8) as u8);
+        self.push_to_stack(v as u8);
+    }
+
+    fn pop_u16_from_stack(&mut self) -> u16{
+        let lo = self.pop_from_stack();
+        let hi = self.pop_from_stack();
+        two_u8_to_u16(hi, lo)
+    }
+
+    // pulls P, discarding the break bit and forcing the unused bit as the real register does
+    fn pop_status(&mut self){
+        self.p = (self.pop_from_stack() & !(1 << BREAK_FLAG)) | (1 << UNUSED_FLAG);
+    }
+
+    fn set_bitflag(&mut self, pos:usize, val:bool){
+        let positions:[u8;8] = [
+            0b00000001,
+            0b00000010,
+            0b00000100,
+            0b00001000,
+            0b00010000,
+            0b00100000,
+            0b01000000,
+            0b10000000
+        ];
+        if(self.p & positions[pos] == positions[pos]) && !val{
+            self.p ^= positions[pos];
+        }
+        else if (self.p & positions[pos] != positions[pos]) && val{
+            self.p |= positions[pos];
+        }
+    }
+
+
+}
+
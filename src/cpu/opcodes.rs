@@ -0,0 +1,382 @@
+// 6502 opcode table
+// Maps every opcode byte to its mnemonic, addressing mode, length, timing and handler
+
+use super::Cpu;
+
+// Addressing modes
+// Determines where an instruction finds its operand
+#[derive(Clone, Copy, PartialEq)]
+pub enum AddressingMode {
+    Implied,     // `CLC`
+    Accumulator, // `LSR A`
+    Immediate,   // `ORA #$B2`
+    ZeroPage,    // `LDA $35`
+    ZeroPageX,   // `LDA $35,x`
+    ZeroPageY,   // `LDX $35,y`
+    Relative,    // `BNE $10` (branches only)
+    Absolute,    // `JMP $4032`
+    AbsoluteX,   // `LDA $4032,x`
+    AbsoluteY,   // `LDA $4032,y`
+    Indirect,    // `JMP ($1000)` (JMP only)
+    IndirectX,   // `LDA ($20,x)`
+    IndirectY,   // `LDA ($20),y`
+}
+
+impl AddressingMode {
+    // instruction length in bytes, including the opcode
+    pub const fn bytes(self) -> u8 {
+        match self {
+            AddressingMode::Implied | AddressingMode::Accumulator => 1,
+            AddressingMode::Absolute | AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::Indirect => 3,
+            _ => 2,
+        }
+    }
+}
+
+// Resolved operand passed to an instruction handler
+#[derive(Clone, Copy)]
+pub struct Operand {
+    pub mode: AddressingMode,
+    pub addr: u16, // effective address (branch target for relative, unused for implied/accumulator)
+    pub page_crossed: bool, // indexing (or a taken branch) carried into another page
+}
+
+pub type Handler = fn(&mut Cpu, Operand);
+
+// Opcode table entry
+#[derive(Clone, Copy)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub mode: AddressingMode,
+    pub bytes: u8,
+    pub cycles: u8, // base cycle count
+    pub page_cross_penalty: bool, // takes an extra cycle when indexing crosses a page
+    pub handler: Handler,
+}
+
+impl Opcode {
+    const fn new(mnemonic:&'static str, mode:AddressingMode, cycles:u8, handler:Handler) -> Opcode {
+        Opcode { mnemonic, mode, bytes: mode.bytes(), cycles, page_cross_penalty: false, handler }
+    }
+
+    const fn page_cross_penalty(mut self) -> Opcode {
+        self.page_cross_penalty = true;
+        self
+    }
+}
+
+pub static OPCODES: [Opcode; 256] = opcode_table();
+
+// Builds the 256 entry opcode table
+const fn opcode_table() -> [Opcode; 256] {
+    use self::AddressingMode::*;
+    // every entry is filled in below, should one be missed it locks up the cpu like the HLT opcodes
+    let mut t = [Opcode::new("HLT", Implied, 2, Cpu::hlt); 256];
+
+    t[0x69] = Opcode::new("ADC", Immediate, 2, Cpu::adc);
+    t[0x65] = Opcode::new("ADC", ZeroPage, 3, Cpu::adc);
+    t[0x75] = Opcode::new("ADC", ZeroPageX, 4, Cpu::adc);
+    t[0x6D] = Opcode::new("ADC", Absolute, 4, Cpu::adc);
+    t[0x7D] = Opcode::new("ADC", AbsoluteX, 4, Cpu::adc).page_cross_penalty();
+    t[0x79] = Opcode::new("ADC", AbsoluteY, 4, Cpu::adc).page_cross_penalty();
+    t[0x61] = Opcode::new("ADC", IndirectX, 6, Cpu::adc);
+    t[0x71] = Opcode::new("ADC", IndirectY, 5, Cpu::adc).page_cross_penalty();
+
+    t[0x29] = Opcode::new("AND", Immediate, 2, Cpu::and);
+    t[0x25] = Opcode::new("AND", ZeroPage, 3, Cpu::and);
+    t[0x35] = Opcode::new("AND", ZeroPageX, 4, Cpu::and);
+    t[0x2D] = Opcode::new("AND", Absolute, 4, Cpu::and);
+    t[0x3D] = Opcode::new("AND", AbsoluteX, 4, Cpu::and).page_cross_penalty();
+    t[0x39] = Opcode::new("AND", AbsoluteY, 4, Cpu::and).page_cross_penalty();
+    t[0x21] = Opcode::new("AND", IndirectX, 6, Cpu::and);
+    t[0x31] = Opcode::new("AND", IndirectY, 5, Cpu::and).page_cross_penalty();
+
+    t[0x0A] = Opcode::new("ASL", Accumulator, 2, Cpu::asl);
+    t[0x06] = Opcode::new("ASL", ZeroPage, 5, Cpu::asl);
+    t[0x16] = Opcode::new("ASL", ZeroPageX, 6, Cpu::asl);
+    t[0x0E] = Opcode::new("ASL", Absolute, 6, Cpu::asl);
+    t[0x1E] = Opcode::new("ASL", AbsoluteX, 7, Cpu::asl);
+
+    t[0x90] = Opcode::new("BCC", Relative, 2, Cpu::bcc);
+    t[0xB0] = Opcode::new("BCS", Relative, 2, Cpu::bcs);
+    t[0xF0] = Opcode::new("BEQ", Relative, 2, Cpu::beq);
+    t[0xD0] = Opcode::new("BNE", Relative, 2, Cpu::bne);
+    t[0x30] = Opcode::new("BMI", Relative, 2, Cpu::bmi);
+    t[0x10] = Opcode::new("BPL", Relative, 2, Cpu::bpl);
+    t[0x50] = Opcode::new("BVC", Relative, 2, Cpu::bvc);
+    t[0x70] = Opcode::new("BVS", Relative, 2, Cpu::bvs);
+
+    t[0x24] = Opcode::new("BIT", ZeroPage, 3, Cpu::bit);
+    t[0x2C] = Opcode::new("BIT", Absolute, 4, Cpu::bit);
+
+    t[0x00] = Opcode::new("BRK", Implied, 7, Cpu::brk);
+
+    t[0x18] = Opcode::new("CLC", Implied, 2, Cpu::clc);
+    t[0x38] = Opcode::new("SEC", Implied, 2, Cpu::sec);
+    t[0x58] = Opcode::new("CLI", Implied, 2, Cpu::cli);
+    t[0x78] = Opcode::new("SEI", Implied, 2, Cpu::sei);
+    t[0xB8] = Opcode::new("CLV", Implied, 2, Cpu::clv);
+    t[0xD8] = Opcode::new("CLD", Implied, 2, Cpu::cld);
+    t[0xF8] = Opcode::new("SED", Implied, 2, Cpu::sed);
+
+    t[0xC9] = Opcode::new("CMP", Immediate, 2, Cpu::cmp);
+    t[0xC5] = Opcode::new("CMP", ZeroPage, 3, Cpu::cmp);
+    t[0xD5] = Opcode::new("CMP", ZeroPageX, 4, Cpu::cmp);
+    t[0xCD] = Opcode::new("CMP", Absolute, 4, Cpu::cmp);
+    t[0xDD] = Opcode::new("CMP", AbsoluteX, 4, Cpu::cmp).page_cross_penalty();
+    t[0xD9] = Opcode::new("CMP", AbsoluteY, 4, Cpu::cmp).page_cross_penalty();
+    t[0xC1] = Opcode::new("CMP", IndirectX, 6, Cpu::cmp);
+    t[0xD1] = Opcode::new("CMP", IndirectY, 5, Cpu::cmp).page_cross_penalty();
+
+    t[0xE0] = Opcode::new("CPX", Immediate, 2, Cpu::cpx);
+    t[0xE4] = Opcode::new("CPX", ZeroPage, 3, Cpu::cpx);
+    t[0xEC] = Opcode::new("CPX", Absolute, 4, Cpu::cpx);
+
+    t[0xC0] = Opcode::new("CPY", Immediate, 2, Cpu::cpy);
+    t[0xC4] = Opcode::new("CPY", ZeroPage, 3, Cpu::cpy);
+    t[0xCC] = Opcode::new("CPY", Absolute, 4, Cpu::cpy);
+
+    t[0xC6] = Opcode::new("DEC", ZeroPage, 5, Cpu::dec);
+    t[0xD6] = Opcode::new("DEC", ZeroPageX, 6, Cpu::dec);
+    t[0xCE] = Opcode::new("DEC", Absolute, 6, Cpu::dec);
+    t[0xDE] = Opcode::new("DEC", AbsoluteX, 7, Cpu::dec);
+
+    t[0xCA] = Opcode::new("DEX", Implied, 2, Cpu::dex);
+    t[0x88] = Opcode::new("DEY", Implied, 2, Cpu::dey);
+
+    t[0x49] = Opcode::new("EOR", Immediate, 2, Cpu::eor);
+    t[0x45] = Opcode::new("EOR", ZeroPage, 3, Cpu::eor);
+    t[0x55] = Opcode::new("EOR", ZeroPageX, 4, Cpu::eor);
+    t[0x4D] = Opcode::new("EOR", Absolute, 4, Cpu::eor);
+    t[0x5D] = Opcode::new("EOR", AbsoluteX, 4, Cpu::eor).page_cross_penalty();
+    t[0x59] = Opcode::new("EOR", AbsoluteY, 4, Cpu::eor).page_cross_penalty();
+    t[0x41] = Opcode::new("EOR", IndirectX, 6, Cpu::eor);
+    t[0x51] = Opcode::new("EOR", IndirectY, 5, Cpu::eor).page_cross_penalty();
+
+    t[0xE6] = Opcode::new("INC", ZeroPage, 5, Cpu::inc);
+    t[0xF6] = Opcode::new("INC", ZeroPageX, 6, Cpu::inc);
+    t[0xEE] = Opcode::new("INC", Absolute, 6, Cpu::inc);
+    t[0xFE] = Opcode::new("INC", AbsoluteX, 7, Cpu::inc);
+
+    t[0xE8] = Opcode::new("INX", Implied, 2, Cpu::inx);
+    t[0xC8] = Opcode::new("INY", Implied, 2, Cpu::iny);
+
+    t[0x4C] = Opcode::new("JMP", Absolute, 3, Cpu::jmp);
+    t[0x6C] = Opcode::new("JMP", Indirect, 5, Cpu::jmp);
+    t[0x20] = Opcode::new("JSR", Absolute, 6, Cpu::jsr);
+
+    t[0xA9] = Opcode::new("LDA", Immediate, 2, Cpu::lda);
+    t[0xA5] = Opcode::new("LDA", ZeroPage, 3, Cpu::lda);
+    t[0xB5] = Opcode::new("LDA", ZeroPageX, 4, Cpu::lda);
+    t[0xAD] = Opcode::new("LDA", Absolute, 4, Cpu::lda);
+    t[0xBD] = Opcode::new("LDA", AbsoluteX, 4, Cpu::lda).page_cross_penalty();
+    t[0xB9] = Opcode::new("LDA", AbsoluteY, 4, Cpu::lda).page_cross_penalty();
+    t[0xA1] = Opcode::new("LDA", IndirectX, 6, Cpu::lda);
+    t[0xB1] = Opcode::new("LDA", IndirectY, 5, Cpu::lda).page_cross_penalty();
+
+    t[0xA2] = Opcode::new("LDX", Immediate, 2, Cpu::ldx);
+    t[0xA6] = Opcode::new("LDX", ZeroPage, 3, Cpu::ldx);
+    t[0xB6] = Opcode::new("LDX", ZeroPageY, 4, Cpu::ldx);
+    t[0xAE] = Opcode::new("LDX", Absolute, 4, Cpu::ldx);
+    t[0xBE] = Opcode::new("LDX", AbsoluteY, 4, Cpu::ldx).page_cross_penalty();
+
+    t[0xA0] = Opcode::new("LDY", Immediate, 2, Cpu::ldy);
+    t[0xA4] = Opcode::new("LDY", ZeroPage, 3, Cpu::ldy);
+    t[0xB4] = Opcode::new("LDY", ZeroPageX, 4, Cpu::ldy);
+    t[0xAC] = Opcode::new("LDY", Absolute, 4, Cpu::ldy);
+    t[0xBC] = Opcode::new("LDY", AbsoluteX, 4, Cpu::ldy).page_cross_penalty();
+
+    t[0x4A] = Opcode::new("LSR", Accumulator, 2, Cpu::lsr);
+    t[0x46] = Opcode::new("LSR", ZeroPage, 5, Cpu::lsr);
+    t[0x56] = Opcode::new("LSR", ZeroPageX, 6, Cpu::lsr);
+    t[0x4E] = Opcode::new("LSR", Absolute, 6, Cpu::lsr);
+    t[0x5E] = Opcode::new("LSR", AbsoluteX, 7, Cpu::lsr);
+
+    t[0xEA] = Opcode::new("NOP", Implied, 2, Cpu::nop);
+
+    t[0x09] = Opcode::new("ORA", Immediate, 2, Cpu::ora);
+    t[0x05] = Opcode::new("ORA", ZeroPage, 3, Cpu::ora);
+    t[0x15] = Opcode::new("ORA", ZeroPageX, 4, Cpu::ora);
+    t[0x0D] = Opcode::new("ORA", Absolute, 4, Cpu::ora);
+    t[0x1D] = Opcode::new("ORA", AbsoluteX, 4, Cpu::ora).page_cross_penalty();
+    t[0x19] = Opcode::new("ORA", AbsoluteY, 4, Cpu::ora).page_cross_penalty();
+    t[0x01] = Opcode::new("ORA", IndirectX, 6, Cpu::ora);
+    t[0x11] = Opcode::new("ORA", IndirectY, 5, Cpu::ora).page_cross_penalty();
+
+    t[0x48] = Opcode::new("PHA", Implied, 3, Cpu::pha);
+    t[0x08] = Opcode::new("PHP", Implied, 3, Cpu::php);
+    t[0x68] = Opcode::new("PLA", Implied, 4, Cpu::pla);
+    t[0x28] = Opcode::new("PLP", Implied, 4, Cpu::plp);
+
+    t[0x2A] = Opcode::new("ROL", Accumulator, 2, Cpu::rol);
+    t[0x26] = Opcode::new("ROL", ZeroPage, 5, Cpu::rol);
+    t[0x36] = Opcode::new("ROL", ZeroPageX, 6, Cpu::rol);
+    t[0x2E] = Opcode::new("ROL", Absolute, 6, Cpu::rol);
+    t[0x3E] = Opcode::new("ROL", AbsoluteX, 7, Cpu::rol);
+
+    t[0x6A] = Opcode::new("ROR", Accumulator, 2, Cpu::ror);
+    t[0x66] = Opcode::new("ROR", ZeroPage, 5, Cpu::ror);
+    t[0x76] = Opcode::new("ROR", ZeroPageX, 6, Cpu::ror);
+    t[0x6E] = Opcode::new("ROR", Absolute, 6, Cpu::ror);
+    t[0x7E] = Opcode::new("ROR", AbsoluteX, 7, Cpu::ror);
+
+    t[0x40] = Opcode::new("RTI", Implied, 6, Cpu::rti);
+    t[0x60] = Opcode::new("RTS", Implied, 6, Cpu::rts);
+
+    t[0xE9] = Opcode::new("SBC", Immediate, 2, Cpu::sbc);
+    t[0xE5] = Opcode::new("SBC", ZeroPage, 3, Cpu::sbc);
+    t[0xF5] = Opcode::new("SBC", ZeroPageX, 4, Cpu::sbc);
+    t[0xED] = Opcode::new("SBC", Absolute, 4, Cpu::sbc);
+    t[0xFD] = Opcode::new("SBC", AbsoluteX, 4, Cpu::sbc).page_cross_penalty();
+    t[0xF9] = Opcode::new("SBC", AbsoluteY, 4, Cpu::sbc).page_cross_penalty();
+    t[0xE1] = Opcode::new("SBC", IndirectX, 6, Cpu::sbc);
+    t[0xF1] = Opcode::new("SBC", IndirectY, 5, Cpu::sbc).page_cross_penalty();
+
+    t[0x85] = Opcode::new("STA", ZeroPage, 3, Cpu::sta);
+    t[0x95] = Opcode::new("STA", ZeroPageX, 4, Cpu::sta);
+    t[0x8D] = Opcode::new("STA", Absolute, 4, Cpu::sta);
+    t[0x9D] = Opcode::new("STA", AbsoluteX, 5, Cpu::sta);
+    t[0x99] = Opcode::new("STA", AbsoluteY, 5, Cpu::sta);
+    t[0x81] = Opcode::new("STA", IndirectX, 6, Cpu::sta);
+    t[0x91] = Opcode::new("STA", IndirectY, 6, Cpu::sta);
+
+    t[0x86] = Opcode::new("STX", ZeroPage, 3, Cpu::stx);
+    t[0x96] = Opcode::new("STX", ZeroPageY, 4, Cpu::stx);
+    t[0x8E] = Opcode::new("STX", Absolute, 4, Cpu::stx);
+
+    t[0x84] = Opcode::new("STY", ZeroPage, 3, Cpu::sty);
+    t[0x94] = Opcode::new("STY", ZeroPageX, 4, Cpu::sty);
+    t[0x8C] = Opcode::new("STY", Absolute, 4, Cpu::sty);
+
+    t[0xAA] = Opcode::new("TAX", Implied, 2, Cpu::tax);
+    t[0xA8] = Opcode::new("TAY", Implied, 2, Cpu::tay);
+    t[0xBA] = Opcode::new("TSX", Implied, 2, Cpu::tsx);
+    t[0x8A] = Opcode::new("TXA", Implied, 2, Cpu::txa);
+    t[0x9A] = Opcode::new("TXS", Implied, 2, Cpu::txs);
+    t[0x98] = Opcode::new("TYA", Implied, 2, Cpu::tya);
+
+    // Unofficial opcodes
+    // Combined read-modify-write and ALU operations
+    t[0x07] = Opcode::new("SLO", ZeroPage, 5, Cpu::slo);
+    t[0x17] = Opcode::new("SLO", ZeroPageX, 6, Cpu::slo);
+    t[0x0F] = Opcode::new("SLO", Absolute, 6, Cpu::slo);
+    t[0x1F] = Opcode::new("SLO", AbsoluteX, 7, Cpu::slo);
+    t[0x1B] = Opcode::new("SLO", AbsoluteY, 7, Cpu::slo);
+    t[0x03] = Opcode::new("SLO", IndirectX, 8, Cpu::slo);
+    t[0x13] = Opcode::new("SLO", IndirectY, 8, Cpu::slo);
+
+    t[0x27] = Opcode::new("RLA", ZeroPage, 5, Cpu::rla);
+    t[0x37] = Opcode::new("RLA", ZeroPageX, 6, Cpu::rla);
+    t[0x2F] = Opcode::new("RLA", Absolute, 6, Cpu::rla);
+    t[0x3F] = Opcode::new("RLA", AbsoluteX, 7, Cpu::rla);
+    t[0x3B] = Opcode::new("RLA", AbsoluteY, 7, Cpu::rla);
+    t[0x23] = Opcode::new("RLA", IndirectX, 8, Cpu::rla);
+    t[0x33] = Opcode::new("RLA", IndirectY, 8, Cpu::rla);
+
+    t[0x47] = Opcode::new("SRE", ZeroPage, 5, Cpu::sre);
+    t[0x57] = Opcode::new("SRE", ZeroPageX, 6, Cpu::sre);
+    t[0x4F] = Opcode::new("SRE", Absolute, 6, Cpu::sre);
+    t[0x5F] = Opcode::new("SRE", AbsoluteX, 7, Cpu::sre);
+    t[0x5B] = Opcode::new("SRE", AbsoluteY, 7, Cpu::sre);
+    t[0x43] = Opcode::new("SRE", IndirectX, 8, Cpu::sre);
+    t[0x53] = Opcode::new("SRE", IndirectY, 8, Cpu::sre);
+
+    t[0x67] = Opcode::new("RRA", ZeroPage, 5, Cpu::rra);
+    t[0x77] = Opcode::new("RRA", ZeroPageX, 6, Cpu::rra);
+    t[0x6F] = Opcode::new("RRA", Absolute, 6, Cpu::rra);
+    t[0x7F] = Opcode::new("RRA", AbsoluteX, 7, Cpu::rra);
+    t[0x7B] = Opcode::new("RRA", AbsoluteY, 7, Cpu::rra);
+    t[0x63] = Opcode::new("RRA", IndirectX, 8, Cpu::rra);
+    t[0x73] = Opcode::new("RRA", IndirectY, 8, Cpu::rra);
+
+    t[0xC7] = Opcode::new("DCP", ZeroPage, 5, Cpu::dcp);
+    t[0xD7] = Opcode::new("DCP", ZeroPageX, 6, Cpu::dcp);
+    t[0xCF] = Opcode::new("DCP", Absolute, 6, Cpu::dcp);
+    t[0xDF] = Opcode::new("DCP", AbsoluteX, 7, Cpu::dcp);
+    t[0xDB] = Opcode::new("DCP", AbsoluteY, 7, Cpu::dcp);
+    t[0xC3] = Opcode::new("DCP", IndirectX, 8, Cpu::dcp);
+    t[0xD3] = Opcode::new("DCP", IndirectY, 8, Cpu::dcp);
+
+    t[0xE7] = Opcode::new("ISC", ZeroPage, 5, Cpu::isc);
+    t[0xF7] = Opcode::new("ISC", ZeroPageX, 6, Cpu::isc);
+    t[0xEF] = Opcode::new("ISC", Absolute, 6, Cpu::isc);
+    t[0xFF] = Opcode::new("ISC", AbsoluteX, 7, Cpu::isc);
+    t[0xFB] = Opcode::new("ISC", AbsoluteY, 7, Cpu::isc);
+    t[0xE3] = Opcode::new("ISC", IndirectX, 8, Cpu::isc);
+    t[0xF3] = Opcode::new("ISC", IndirectY, 8, Cpu::isc);
+
+    t[0x87] = Opcode::new("SAX", ZeroPage, 3, Cpu::sax);
+    t[0x97] = Opcode::new("SAX", ZeroPageY, 4, Cpu::sax);
+    t[0x8F] = Opcode::new("SAX", Absolute, 4, Cpu::sax);
+    t[0x83] = Opcode::new("SAX", IndirectX, 6, Cpu::sax);
+
+    t[0xA7] = Opcode::new("LAX", ZeroPage, 3, Cpu::lax);
+    t[0xB7] = Opcode::new("LAX", ZeroPageY, 4, Cpu::lax);
+    t[0xAF] = Opcode::new("LAX", Absolute, 4, Cpu::lax);
+    t[0xBF] = Opcode::new("LAX", AbsoluteY, 4, Cpu::lax).page_cross_penalty();
+    t[0xA3] = Opcode::new("LAX", IndirectX, 6, Cpu::lax);
+    t[0xB3] = Opcode::new("LAX", IndirectY, 5, Cpu::lax).page_cross_penalty();
+
+    // Immediate combinations
+    t[0x0B] = Opcode::new("ANC", Immediate, 2, Cpu::anc);
+    t[0x2B] = Opcode::new("ANC", Immediate, 2, Cpu::anc);
+    t[0x4B] = Opcode::new("ALR", Immediate, 2, Cpu::alr);
+    t[0x6B] = Opcode::new("ARR", Immediate, 2, Cpu::arr);
+    t[0xCB] = Opcode::new("AXS", Immediate, 2, Cpu::axs);
+    t[0xEB] = Opcode::new("SBC", Immediate, 2, Cpu::sbc);
+    t[0xBB] = Opcode::new("LAS", AbsoluteY, 4, Cpu::las).page_cross_penalty();
+
+    // Unstable on real hardware, these use the commonly observed behaviour
+    t[0x8B] = Opcode::new("ANE", Immediate, 2, Cpu::ane);
+    t[0xAB] = Opcode::new("LXA", Immediate, 2, Cpu::lxa);
+    t[0x9C] = Opcode::new("SHY", AbsoluteX, 5, Cpu::shy);
+    t[0x9E] = Opcode::new("SHX", AbsoluteY, 5, Cpu::shx);
+    t[0x9F] = Opcode::new("SHA", AbsoluteY, 5, Cpu::sha);
+    t[0x93] = Opcode::new("SHA", IndirectY, 6, Cpu::sha);
+    t[0x9B] = Opcode::new("TAS", AbsoluteY, 5, Cpu::tas);
+
+    // NOPs of every size
+    t[0x1A] = Opcode::new("NOP", Implied, 2, Cpu::nop);
+    t[0x3A] = Opcode::new("NOP", Implied, 2, Cpu::nop);
+    t[0x5A] = Opcode::new("NOP", Implied, 2, Cpu::nop);
+    t[0x7A] = Opcode::new("NOP", Implied, 2, Cpu::nop);
+    t[0xDA] = Opcode::new("NOP", Implied, 2, Cpu::nop);
+    t[0xFA] = Opcode::new("NOP", Implied, 2, Cpu::nop);
+    t[0x80] = Opcode::new("DOP", Immediate, 2, Cpu::nop);
+    t[0x82] = Opcode::new("DOP", Immediate, 2, Cpu::nop);
+    t[0x89] = Opcode::new("DOP", Immediate, 2, Cpu::nop);
+    t[0xC2] = Opcode::new("DOP", Immediate, 2, Cpu::nop);
+    t[0xE2] = Opcode::new("DOP", Immediate, 2, Cpu::nop);
+    t[0x04] = Opcode::new("DOP", ZeroPage, 3, Cpu::nop);
+    t[0x44] = Opcode::new("DOP", ZeroPage, 3, Cpu::nop);
+    t[0x64] = Opcode::new("DOP", ZeroPage, 3, Cpu::nop);
+    t[0x14] = Opcode::new("DOP", ZeroPageX, 4, Cpu::nop);
+    t[0x34] = Opcode::new("DOP", ZeroPageX, 4, Cpu::nop);
+    t[0x54] = Opcode::new("DOP", ZeroPageX, 4, Cpu::nop);
+    t[0x74] = Opcode::new("DOP", ZeroPageX, 4, Cpu::nop);
+    t[0xD4] = Opcode::new("DOP", ZeroPageX, 4, Cpu::nop);
+    t[0xF4] = Opcode::new("DOP", ZeroPageX, 4, Cpu::nop);
+    t[0x0C] = Opcode::new("TOP", Absolute, 4, Cpu::nop);
+    t[0x1C] = Opcode::new("TOP", AbsoluteX, 4, Cpu::nop).page_cross_penalty();
+    t[0x3C] = Opcode::new("TOP", AbsoluteX, 4, Cpu::nop).page_cross_penalty();
+    t[0x5C] = Opcode::new("TOP", AbsoluteX, 4, Cpu::nop).page_cross_penalty();
+    t[0x7C] = Opcode::new("TOP", AbsoluteX, 4, Cpu::nop).page_cross_penalty();
+    t[0xDC] = Opcode::new("TOP", AbsoluteX, 4, Cpu::nop).page_cross_penalty();
+    t[0xFC] = Opcode::new("TOP", AbsoluteX, 4, Cpu::nop).page_cross_penalty();
+
+    // Lock up the cpu until reset
+    t[0x02] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x12] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x22] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x32] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x42] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x52] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x62] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x72] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0x92] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0xB2] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0xD2] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+    t[0xF2] = Opcode::new("HLT", Implied, 2, Cpu::hlt);
+
+    t
+}
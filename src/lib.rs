@@ -0,0 +1,22 @@
+// Nintendo Entertainment System Emulator
+// Author: Kyron Taylor
+// ==
+// Library crate holding the emulated hardware, the binary in main.rs is a thin front end
+
+pub mod bus;
+pub mod cartridge;
+pub mod cpu;
+pub mod mapper;
+pub mod nes;
+pub mod ppm;
+pub mod ppu;
+pub mod save;
+mod util;
+
+pub use bus::{Bus, RamInit};
+pub use cartridge::{Cartridge, HeaderFormat, Mirroring, RomError, Timing};
+pub use cpu::{Cpu, CpuVariant};
+pub use mapper::Mapper;
+pub use nes::NESEmulator;
+pub use ppu::Ppu;
+pub use ppu::palette::{Palette, PaletteError};
@@ -0,0 +1,71 @@
+// AxROM (mapper 7)
+// Switchable 32kB PRG bank, 8kB CHR-RAM and single-screen mirroring picked by software
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{bus_conflicts, banked, chr_memory, trainer_ram, Mapper};
+
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>, // only there for a trainer
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    bus_conflicts: bool, // AMROM has them, ANROM and AOROM do not
+    bank: u8, // ...M.PPP: nametable page, PRG bank
+}
+
+impl Axrom {
+    pub fn new(mut cart:Cartridge) -> Axrom {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        Axrom {
+            bus_conflicts: bus_conflicts(&cart, false),
+            prg_ram: trainer_ram(&cart),
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xFFFF => Some(self.prg_rom[banked(&self.prg_rom, (self.bank & 0x07) as usize, 0x8000, addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        if addr >= 0x8000 {
+            let val = if self.bus_conflicts { val & self.cpu_peek(addr).unwrap_or(0xFF) } else { val };
+            self.bank = val;
+        }
+        else if let 0x6000..=0x7FFF = addr {
+            if !self.prg_ram.is_empty() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.chr[addr as usize % self.chr.len()]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let len = self.chr.len();
+            self.chr[addr as usize % len] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if check_bit(self.bank, 4) { Mirroring::SingleScreenUpper } else { Mirroring::SingleScreenLower }
+    }
+}
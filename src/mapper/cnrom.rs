@@ -0,0 +1,73 @@
+// CNROM (mapper 3)
+// Fixed 16kB or 32kB of PRG-ROM, switchable 8kB CHR-ROM bank
+
+use cartridge::{Cartridge, Mirroring};
+use super::{bus_conflicts, banked, board_mirroring, chr_memory, trainer_ram, Mapper};
+
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>, // only there for a trainer
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank: u8,
+}
+
+impl Cnrom {
+    pub fn new(mut cart:Cartridge) -> Cnrom {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        Cnrom {
+            bus_conflicts: bus_conflicts(&cart, true),
+            mirroring: board_mirroring(&cart),
+            prg_ram: trainer_ram(&cart),
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            chr_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xFFFF => Some(self.prg_rom[(addr as usize - 0x8000) % self.prg_rom.len()]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        if addr >= 0x8000 {
+            // the ROM drives the bus at the same time, so a 0 on either side wins
+            let val = if self.bus_conflicts { val & self.cpu_peek(addr).unwrap_or(0xFF) } else { val };
+            self.chr_bank = val;
+        }
+        else if let 0x6000..=0x7FFF = addr {
+            if !self.prg_ram.is_empty() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.chr[banked(&self.chr, self.chr_bank as usize, 0x2000, addr)]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let i = banked(&self.chr, self.chr_bank as usize, 0x2000, addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,206 @@
+// MMC1 (mapper 1)
+// Registers are loaded serially through a 5-bit shift register, one bit per write to $8000 - $FFFF
+// Used by the SxROM boards, some of which reuse the CHR bank lines for extra PRG-ROM and PRG-RAM
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{banked, chr_memory, prg_ram, Mapper};
+
+// Board variants that change what the CHR bank registers drive
+#[derive(Clone, Copy, PartialEq)]
+enum Board {
+    Standard, // SAROM, SKROM, SLROM...: CHR bank registers select CHR only
+    Snrom, // 8kB CHR, bit 4 of the CHR bank disables PRG-RAM
+    Sorom, // 8kB CHR, bit 3 selects one of two 8kB PRG-RAM banks
+    Surom, // 8kB CHR, bit 4 selects the 256kB half of 512kB PRG-ROM
+    Sxrom, // SUROM plus bits 2-3 selecting one of four 8kB PRG-RAM banks
+    Serom, // submapper 5, 32kB PRG-ROM that is never banked
+}
+
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    board: Board,
+
+    // Serial port
+    shift: u8,
+    shift_count: u8,
+    ignore_write: bool, // this write follows another on the very next cycle
+    last_access_write: bool,
+
+    // Internal registers
+    control: u8, // CPPMM: CHR mode, PRG mode, mirroring
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8, // RPPPP: PRG-RAM disable, PRG bank
+}
+
+impl Mmc1 {
+    pub fn new(mut cart:Cartridge) -> Mmc1 {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        let prg_ram = prg_ram(&cart);
+        let board = if cart.submapper == 5 {
+            Board::Serom
+        }
+        else if chr.len() == 0x2000 {
+            // with only 8kB of CHR the upper CHR bank bits are free for other uses
+            match (cart.prg_rom.len() > 0x40000, prg_ram.len()) {
+                (true, n) if n >= 0x8000 => Board::Sxrom,
+                (true, _) => Board::Surom,
+                (false, n) if n >= 0x4000 => Board::Sorom,
+                (false, _) => Board::Snrom,
+            }
+        }
+        else {
+            Board::Standard
+        };
+        Mmc1 {
+            prg_rom: cart.prg_rom,
+            prg_ram,
+            chr,
+            chr_is_ram,
+            board,
+            shift: 0,
+            shift_count: 0,
+            ignore_write: false,
+            last_access_write: false,
+            control: 0x0C, // PRG mode 3 at power on, so the last bank (and reset vector) sits at $C000
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    // Loads a full 5-bit value into the register picked by address bits 13-14
+    fn write_register(&mut self, addr:u16, val:u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = val,
+            0xA000..=0xBFFF => self.chr_bank_0 = val,
+            0xC000..=0xDFFF => self.chr_bank_1 = val,
+            _ => self.prg_bank = val,
+        }
+    }
+
+    // 16kB PRG-ROM bank mapped at addr
+    fn prg_bank_at(&self, addr:u16) -> usize {
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let bank = match (self.control >> 2) & 0x03 {
+            // 32kB mode ignores the low bit
+            0 | 1 => (bank & !1) | ((addr as usize >> 14) & 1),
+            // fix first bank at $8000, switch $C000
+            2 => if addr < 0xC000 { 0 } else { bank },
+            // fix last bank at $C000, switch $8000
+            _ => if addr < 0xC000 { bank } else { 0x0F },
+        };
+        // SUROM and SXROM pick which 256kB half all of that applies to
+        let outer = match self.board {
+            Board::Surom | Board::Sxrom => (self.chr_bank_0 & 0x10) as usize,
+            _ => 0,
+        };
+        outer | bank
+    }
+
+    // 4kB CHR bank mapped at addr
+    fn chr_bank_at(&self, addr:u16) -> usize {
+        if check_bit(self.control, 4) {
+            if addr < 0x1000 { self.chr_bank_0 as usize } else { self.chr_bank_1 as usize }
+        }
+        else {
+            // 8kB mode ignores the low bit of CHR bank 0
+            (self.chr_bank_0 as usize & !1) | ((addr as usize >> 12) & 1)
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        if self.prg_ram.is_empty() || check_bit(self.prg_bank, 4) {
+            return false;
+        }
+        match self.board {
+            Board::Snrom => !check_bit(self.chr_bank_0, 4),
+            _ => true,
+        }
+    }
+
+    // 8kB PRG-RAM bank at $6000
+    fn prg_ram_bank(&self) -> usize {
+        match self.board {
+            Board::Sorom => ((self.chr_bank_0 >> 3) & 0x01) as usize,
+            Board::Sxrom => ((self.chr_bank_0 >> 2) & 0x03) as usize,
+            _ => 0,
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram[banked(&self.prg_ram, self.prg_ram_bank(), 0x2000, addr)]),
+            0x8000..=0xFFFF if self.board == Board::Serom => Some(self.prg_rom[banked(&self.prg_rom, 0, 0x8000, addr)]),
+            0x8000..=0xFFFF => Some(self.prg_rom[banked(&self.prg_rom, self.prg_bank_at(addr), 0x4000, addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => {
+                let i = banked(&self.prg_ram, self.prg_ram_bank(), 0x2000, addr);
+                self.prg_ram[i] = val;
+            },
+            0x8000..=0xFFFF => {
+                // the serial port only samples the first of back to back writes (the dummy write of INC/DEC etc)
+                if self.ignore_write {
+                    return;
+                }
+                if check_bit(val, 7) {
+                    // bit 7 resets the shift register and locks PRG mode 3
+                    self.shift = 0;
+                    self.shift_count = 0;
+                    self.control |= 0x0C;
+                    return;
+                }
+                // bits arrive LSB first
+                self.shift |= (val & 0x01) << self.shift_count;
+                self.shift_count += 1;
+                if self.shift_count == 5 {
+                    let value = self.shift;
+                    self.write_register(addr, value);
+                    self.shift = 0;
+                    self.shift_count = 0;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn cpu_access(&mut self, write:bool) {
+        self.ignore_write = write && self.last_access_write;
+        self.last_access_write = write;
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.chr[banked(&self.chr, self.chr_bank_at(addr), 0x1000, addr)]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let i = banked(&self.chr, self.chr_bank_at(addr), 0x1000, addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+}
@@ -0,0 +1,119 @@
+// MMC2 (mapper 9) and MMC4 (mapper 10)
+// Each 4kB pattern table has two CHR banks, picked by a latch the PPU flips when it fetches tile $FD or $FE
+// MMC2 (PxROM) has a switchable 8kB PRG bank, MMC4 (FxROM) a 16kB one and PRG-RAM
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{banked, chr_memory, prg_ram, trainer_ram, Mapper};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Chip {
+    Mmc2,
+    Mmc4,
+}
+
+pub struct Mmc2 {
+    chip: Chip,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    prg_bank: u8,
+    chr_banks: [[u8; 2]; 2], // [pattern table][latch $FD, latch $FE]
+    latches: [usize; 2], // 0 = $FD, 1 = $FE
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(mut cart:Cartridge) -> Mmc2 {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        let chip = if cart.mapper == 10 { Chip::Mmc4 } else { Chip::Mmc2 };
+        Mmc2 {
+            chip,
+            // only the MMC4 boards carry PRG-RAM, MMC2 ones get just enough for a trainer
+            prg_ram: if chip == Chip::Mmc4 { prg_ram(&cart) } else { trainer_ram(&cart) },
+            mirroring: cart.mirroring,
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [1, 1],
+        }
+    }
+
+    // Index into CHR of a pattern table address, through the bank its latch picks
+    fn chr_index(&self, addr:u16) -> usize {
+        let table = (addr as usize >> 12) & 1;
+        banked(&self.chr, self.chr_banks[table][self.latches[table]] as usize, 0x1000, addr)
+    }
+
+    // The latch flips after the fetch, so the tile that triggers it still comes from the old bank
+    fn update_latch(&mut self, addr:u16) {
+        // MMC2 only reacts to the first byte of the left table's trigger tiles, MMC4 to all of them
+        let left = match self.chip {
+            Chip::Mmc2 => addr,
+            Chip::Mmc4 => addr & 0xFFF8,
+        };
+        match (addr & 0x1FF8, left) {
+            (_, 0x0FD8) => self.latches[0] = 0,
+            (_, 0x0FE8) => self.latches[0] = 1,
+            (0x1FD8, _) => self.latches[1] = 0,
+            (0x1FE8, _) => self.latches[1] = 1,
+            _ => {},
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        let last = self.prg_rom.len() / 0x2000;
+        match (addr, self.chip) {
+            (0x6000..=0x7FFF, _) if !self.prg_ram.is_empty() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            (0x8000..=0x9FFF, Chip::Mmc2) => Some(self.prg_rom[banked(&self.prg_rom, self.prg_bank as usize, 0x2000, addr)]),
+            // the last three 8kB banks stay put
+            (0xA000..=0xFFFF, Chip::Mmc2) => Some(self.prg_rom[banked(&self.prg_rom, (last + ((addr as usize - 0xA000) >> 13)).saturating_sub(3), 0x2000, addr)]),
+            (0x8000..=0xBFFF, Chip::Mmc4) => Some(self.prg_rom[banked(&self.prg_rom, self.prg_bank as usize, 0x4000, addr)]),
+            (0xC000..=0xFFFF, Chip::Mmc4) => Some(self.prg_rom[banked(&self.prg_rom, (last / 2).saturating_sub(1), 0x4000, addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            },
+            0xA000..=0xAFFF => self.prg_bank = val & 0x0F,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = val & 0x1F,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = val & 0x1F,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = val & 0x1F,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = val & 0x1F,
+            0xF000..=0xFFFF => self.mirroring = if check_bit(val, 0) { Mirroring::Horizontal } else { Mirroring::Vertical },
+            _ => {},
+        }
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        let val = self.chr[self.chr_index(addr)];
+        self.update_latch(addr);
+        val
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let i = self.chr_index(addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,187 @@
+// MMC3 (mapper 4)
+// Four 8kB PRG banks, eight CHR banks of 1kB/2kB, and a scanline counter driven by PPU address line A12
+// Used by the TxROM boards
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{banked, chr_memory, prg_ram, Mapper, MapperOptions};
+
+// Pattern reads A12 has to stay low for before a rise clocks the counter
+// Stands in for the M2 based filter of the real chip, so the back and forth of sprite fetches is not counted
+const A12_FILTER: u8 = 3;
+
+// How the counter behaves when it reaches zero, which differs between chip revisions
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum IrqBehaviour {
+    Sharp, // MMC3B/C: an IRQ every time the counter is zero after clocking
+    Nec, // MMC3A: reloading to zero only fires when the reload was requested through $C001
+}
+
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    four_screen: bool,
+
+    bank_select: u8, // CP...RRR: CHR A12 inversion, PRG mode, register to update
+    registers: [u8; 8], // R0-R5 CHR banks, R6-R7 PRG banks
+    mirroring: Mirroring,
+    prg_ram_protect: u8, // ER......: chip enable, write deny
+
+    // Scanline counter
+    irq_behaviour: IrqBehaviour,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_low_reads: u8,
+}
+
+impl Mmc3 {
+    pub fn new(mut cart:Cartridge, options:&MapperOptions) -> Mmc3 {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        Mmc3 {
+            prg_ram: prg_ram(&cart),
+            four_screen: cart.four_screen,
+            mirroring: cart.mirroring,
+            // unless overridden, NES 2.0 submapper 4 marks the MMC3A behaviour
+            irq_behaviour: options.mmc3_irq.unwrap_or(if cart.submapper == 4 { IrqBehaviour::Nec } else { IrqBehaviour::Sharp }),
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            bank_select: 0,
+            registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            prg_ram_protect: 0x80, // undefined at power up, enabled so games that never touch $A001 keep their RAM
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_low_reads: 0,
+        }
+    }
+
+    // 8kB PRG bank mapped at addr
+    fn prg_bank_at(&self, addr:u16) -> usize {
+        let last = (self.prg_rom.len() / 0x2000).saturating_sub(1);
+        let slot = (addr as usize - 0x8000) / 0x2000;
+        // PRG mode swaps which of $8000 and $C000 is fixed to the second last bank
+        let slot = if check_bit(self.bank_select, 6) && slot & 1 == 0 { slot ^ 2 } else { slot };
+        match slot {
+            0 => self.registers[6] as usize,
+            1 => self.registers[7] as usize,
+            2 => last.saturating_sub(1),
+            _ => last,
+        }
+    }
+
+    // 1kB CHR bank mapped at addr
+    fn chr_bank_at(&self, addr:u16) -> usize {
+        // A12 inversion swaps the 2kB and 1kB halves
+        let addr = if check_bit(self.bank_select, 7) { addr ^ 0x1000 } else { addr };
+        let slot = (addr as usize & 0x1FFF) / 0x400;
+        match slot {
+            0 | 1 => (self.registers[0] as usize & !1) | slot,
+            2 | 3 => (self.registers[1] as usize & !1) | (slot & 1),
+            _ => self.registers[slot - 2] as usize,
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        !self.prg_ram.is_empty() && check_bit(self.prg_ram_protect, 7)
+    }
+
+    // Watches PPU address line A12, clocking the counter as it rises
+    fn watch_a12(&mut self, addr:u16) {
+        if addr & 0x1000 == 0 {
+            self.a12_low_reads = self.a12_low_reads.saturating_add(1);
+            return;
+        }
+        if self.a12_low_reads >= A12_FILTER {
+            self.clock_counter();
+        }
+        self.a12_low_reads = 0;
+    }
+
+    fn clock_counter(&mut self) {
+        let before = self.irq_counter;
+        let reload = self.irq_reload;
+        if self.irq_counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+            self.irq_reload = false;
+        }
+        else {
+            self.irq_counter -= 1;
+        }
+        let fire = match self.irq_behaviour {
+            IrqBehaviour::Sharp => self.irq_counter == 0,
+            IrqBehaviour::Nec => self.irq_counter == 0 && (before != 0 || reload),
+        };
+        if fire && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xFFFF => Some(self.prg_rom[banked(&self.prg_rom, self.prg_bank_at(addr), 0x2000, addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        let even = addr & 1 == 0;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() && !check_bit(self.prg_ram_protect, 6) => {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            },
+            0x8000..=0x9FFF if even => self.bank_select = val,
+            0x8000..=0x9FFF => self.registers[(self.bank_select & 0x07) as usize] = val,
+            0xA000..=0xBFFF if even => self.mirroring = if check_bit(val, 0) { Mirroring::Horizontal } else { Mirroring::Vertical },
+            0xA000..=0xBFFF => self.prg_ram_protect = val,
+            0xC000..=0xDFFF if even => self.irq_latch = val,
+            0xC000..=0xDFFF => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            },
+            0xE000..=0xFFFF if even => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            },
+            0xE000..=0xFFFF => self.irq_enabled = true,
+            _ => {},
+        }
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.watch_a12(addr);
+        self.chr[banked(&self.chr, self.chr_bank_at(addr), 0x400, addr)]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        self.watch_a12(addr);
+        if self.chr_is_ram {
+            let i = banked(&self.chr, self.chr_bank_at(addr), 0x400, addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        // TVROM and friends wire up their own nametable RAM and ignore $A000
+        if self.four_screen { Mirroring::FourScreen } else { self.mirroring }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+}
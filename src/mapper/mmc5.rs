@@ -0,0 +1,437 @@
+// MMC5 (mapper 5)
+// Flexible PRG/CHR banking, 1kB of ExRAM, fill mode, extended attributes, a vertical split,
+// an 8x8 multiplier and a scanline IRQ
+// The chip has no scanline input, it works out where the PPU is by watching its fetches:
+// three reads of the same nametable address end every line, after which the fetches follow a fixed pattern
+// The expansion audio is not emulated
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{banked, chr_memory, prg_ram, Mapper};
+
+// Nametable fetches (tile and attribute) in one line, counting from the first after the line starts
+const BG_FETCHES: u16 = 64; // 32 tiles for the visible part
+const SPRITE_FETCHES: u16 = 80; // 8 sprites, two garbage fetches each
+const PREFETCHES: u16 = 84; // first two tiles of the next line
+
+pub struct Mmc5 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    exram: [u8; 0x400],
+
+    // Configuration
+    prg_mode: u8,
+    chr_mode: u8,
+    prg_ram_protect: [u8; 2], // unlocked by writing $02 to $5102 and $01 to $5103
+    exram_mode: u8, // 0 = nametable, 1 = extended attributes, 2 = RAM, 3 = read only RAM
+    nametables: u8, // source for each nametable: CIRAM page 0/1, ExRAM, fill
+    fill_tile: u8,
+    fill_attribute: u8,
+
+    // Banks
+    prg_banks: [u8; 5], // $5113 - $5117
+    chr_banks: [u16; 12], // $5120 - $512B, sprite set (A) then background set (B)
+    chr_upper: u8, // $5130, upper bits for the next CHR bank write
+    last_chr_set_b: bool,
+
+    // Vertical split
+    split_control: u8, // ESTTTTT: enable, right side, tile count
+    split_scroll: u8,
+    split_bank: u8,
+
+    // Scanline IRQ
+    irq_compare: u8,
+    irq_enabled: bool,
+    irq_pending: bool,
+    in_frame: bool,
+    scanline: u8,
+
+    // Multiplier
+    multiplicand: u8,
+    multiplier: u8,
+
+    // Snooped from the PPU registers
+    sprite_8x16: bool,
+
+    // Fetch tracking
+    last_nametable_addr: u16,
+    nametable_repeats: u8,
+    fetches: u16, // nametable fetches since the line started
+    ext_tile: u8, // ExRAM byte belonging to the tile being fetched
+    split_tile: bool, // tile being fetched is inside the split
+    split_column: usize,
+    split_y: usize,
+}
+
+impl Mmc5 {
+    pub fn new(mut cart:Cartridge) -> Mmc5 {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        Mmc5 {
+            prg_ram: prg_ram(&cart),
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            exram: [0; 0x400],
+            prg_mode: 3,
+            chr_mode: 0,
+            prg_ram_protect: [0; 2],
+            exram_mode: 0,
+            nametables: 0,
+            fill_tile: 0,
+            fill_attribute: 0,
+            prg_banks: [0, 0, 0, 0, 0xFF], // the last bank is mapped to $E000 at power on
+            chr_banks: [0; 12],
+            chr_upper: 0,
+            last_chr_set_b: false,
+            split_control: 0,
+            split_scroll: 0,
+            split_bank: 0,
+            irq_compare: 0,
+            irq_enabled: false,
+            irq_pending: false,
+            in_frame: false,
+            scanline: 0,
+            multiplicand: 0xFF,
+            multiplier: 0xFF,
+            sprite_8x16: false,
+            last_nametable_addr: 0,
+            nametable_repeats: 0,
+            fetches: 0,
+            ext_tile: 0,
+            split_tile: false,
+            split_column: 0,
+            split_y: 0,
+        }
+    }
+
+    // PRG-ROM or PRG-RAM and the 8kB bank within it mapped at addr
+    fn prg_target(&self, addr:u16) -> (bool, usize) {
+        if addr < 0x8000 {
+            return (false, (self.prg_banks[0] & 0x07) as usize);
+        }
+        let slot = (addr as usize - 0x8000) >> 13;
+        // register and how many low bank bits the slot size replaces with address lines
+        let (reg, mask) = match (self.prg_mode, slot) {
+            (0, _) => (4, 0x03),
+            (1, 0..=1) => (2, 0x01),
+            (1, _) => (4, 0x01),
+            (2, 0..=1) => (2, 0x01),
+            (2, 2) => (3, 0x00),
+            (2, _) => (4, 0x00),
+            (_, 3) => (4, 0x00),
+            (_, _) => (slot + 1, 0x00),
+        };
+        let val = self.prg_banks[reg];
+        // $5117 is always ROM, the others pick with bit 7
+        let rom = reg == 4 || check_bit(val, 7);
+        let bank = ((val & 0x7F) as usize & !mask) | (slot & mask);
+        if rom { (true, bank) } else { (false, bank & 0x07) }
+    }
+
+    fn prg_ram_writable(&self) -> bool {
+        self.prg_ram_protect[0] & 0x03 == 0x02 && self.prg_ram_protect[1] & 0x03 == 0x01
+    }
+
+    // Bank (in units of the current CHR mode) and bank size for addr, from the sprite or background set
+    fn chr_bank_at(&self, addr:u16, set_b:bool) -> (usize, usize) {
+        let slot = (addr as usize >> 10) & 0x07;
+        let r = &self.chr_banks;
+        let (bank, size) = match (self.chr_mode, set_b) {
+            (0, false) => (r[7], 0x2000),
+            (0, true) => (r[11], 0x2000),
+            (1, false) => (if slot < 4 { r[3] } else { r[7] }, 0x1000),
+            (1, true) => (r[11], 0x1000),
+            (2, false) => (r[(slot >> 1) * 2 + 1], 0x800),
+            (2, true) => (r[8 + ((slot >> 1) & 1) * 2 + 1], 0x800),
+            (_, false) => (r[slot], 0x400),
+            (_, true) => (r[8 + (slot & 3)], 0x400),
+        };
+        (bank as usize, size)
+    }
+
+    // Index into CHR for a pattern fetch, following the split, extended attributes and the two register sets
+    fn chr_index(&self, addr:u16) -> usize {
+        let background = self.in_frame && !self.sprite_phase();
+        if background && self.split_tile {
+            let addr = (addr & 0x0FF8) | (self.split_y & 0x07) as u16;
+            return banked(&self.chr, self.split_bank as usize, 0x1000, addr);
+        }
+        if background && self.exram_mode == 1 {
+            let bank = ((self.chr_upper as usize) << 6) | (self.ext_tile & 0x3F) as usize;
+            return banked(&self.chr, bank, 0x1000, addr);
+        }
+        // with 8x16 sprites the sprite set (A) feeds sprites and the background set (B) the background,
+        // otherwise whichever set was written last is used for everything
+        let set_b = if self.sprite_8x16 && self.in_frame { !self.sprite_phase() } else { self.last_chr_set_b };
+        let (bank, size) = self.chr_bank_at(addr, set_b);
+        banked(&self.chr, bank, size, addr)
+    }
+
+    fn sprite_phase(&self) -> bool {
+        self.fetches > BG_FETCHES && self.fetches <= SPRITE_FETCHES
+    }
+
+    // Three reads of one nametable address in a row, with no pattern fetch between them, mark the start of a line
+    fn track_nametable(&mut self, addr:u16) {
+        if addr == self.last_nametable_addr {
+            self.nametable_repeats = self.nametable_repeats.saturating_add(1);
+            if self.nametable_repeats == 2 {
+                self.start_scanline();
+            }
+        }
+        else {
+            self.last_nametable_addr = addr;
+            self.nametable_repeats = 0;
+        }
+        self.fetches = self.fetches.saturating_add(1);
+    }
+
+    fn start_scanline(&mut self) {
+        if self.in_frame {
+            self.scanline = self.scanline.wrapping_add(1);
+        }
+        else {
+            self.in_frame = true;
+            self.scanline = 0;
+        }
+        if self.irq_compare != 0 && self.scanline == self.irq_compare {
+            self.irq_pending = true;
+        }
+        self.fetches = 0;
+    }
+
+    // Works out whether the background tile just fetched falls inside the split, and where in it
+    fn update_split(&mut self) {
+        self.split_tile = false;
+        if !check_bit(self.split_control, 7) || self.exram_mode > 1 {
+            return;
+        }
+        // the two prefetched tiles are the first of the next line
+        let (column, line) = match self.fetches {
+            1..=BG_FETCHES => ((self.fetches as usize - 1) / 2 + 2, self.scanline as usize),
+            n if n > SPRITE_FETCHES && n <= PREFETCHES => ((n - SPRITE_FETCHES - 1) as usize / 2, self.scanline as usize + 1),
+            _ => return,
+        };
+        let count = (self.split_control & 0x1F) as usize;
+        self.split_tile = if check_bit(self.split_control, 6) { column >= count } else { column < count };
+        self.split_column = column & 0x1F;
+        self.split_y = (self.split_scroll as usize + line) % 240;
+    }
+}
+
+impl Mapper for Mmc5 {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x5204 => Some(((self.irq_pending as u8) << 7) | ((self.in_frame as u8) << 6)),
+            0x5205 => Some((self.multiplicand as u16 * self.multiplier as u16) as u8),
+            0x5206 => Some(((self.multiplicand as u16 * self.multiplier as u16) >> 8) as u8),
+            0x5C00..=0x5FFF if self.exram_mode >= 2 => Some(self.exram[addr as usize - 0x5C00]),
+            0x6000..=0xFFFF => {
+                let (rom, bank) = self.prg_target(addr);
+                if rom {
+                    Some(self.prg_rom[banked(&self.prg_rom, bank, 0x2000, addr)])
+                }
+                else if !self.prg_ram.is_empty() {
+                    Some(self.prg_ram[banked(&self.prg_ram, bank, 0x2000, addr)])
+                }
+                else {
+                    None
+                }
+            },
+            _ => None,
+        }
+    }
+
+    fn cpu_read(&mut self, addr:u16) -> Option<u8> {
+        let val = self.cpu_peek(addr);
+        match addr {
+            // reading the status acknowledges the IRQ
+            0x5204 => self.irq_pending = false,
+            // fetching the NMI vector means vblank, the frame is over
+            0xFFFA | 0xFFFB => {
+                self.in_frame = false;
+                self.last_nametable_addr = 0;
+            },
+            _ => {},
+        }
+        val
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        match addr {
+            // PPUCTRL and PPUMASK are watched for the sprite size and rendering
+            0x2000..=0x3FFF if addr & 0x07 == 0 => self.sprite_8x16 = check_bit(val, 5),
+            0x2000..=0x3FFF if addr & 0x07 == 1 && val & 0x18 == 0 => self.in_frame = false,
+            0x5100 => self.prg_mode = val & 0x03,
+            0x5101 => self.chr_mode = val & 0x03,
+            0x5102 | 0x5103 => self.prg_ram_protect[addr as usize - 0x5102] = val,
+            0x5104 => self.exram_mode = val & 0x03,
+            0x5105 => self.nametables = val,
+            0x5106 => self.fill_tile = val,
+            0x5107 => self.fill_attribute = val & 0x03,
+            0x5113..=0x5117 => self.prg_banks[addr as usize - 0x5113] = val,
+            0x5120..=0x512B => {
+                let reg = addr as usize - 0x5120;
+                self.chr_banks[reg] = val as u16 | ((self.chr_upper as u16) << 8);
+                self.last_chr_set_b = reg >= 8;
+            },
+            0x5130 => self.chr_upper = val & 0x03,
+            0x5200 => self.split_control = val,
+            0x5201 => self.split_scroll = val,
+            0x5202 => self.split_bank = val,
+            0x5203 => self.irq_compare = val,
+            0x5204 => self.irq_enabled = check_bit(val, 7),
+            0x5205 => self.multiplicand = val,
+            0x5206 => self.multiplier = val,
+            // as a nametable ExRAM can only be written while rendering, other writes store 0
+            0x5C00..=0x5FFF => match self.exram_mode {
+                0 | 1 => self.exram[addr as usize - 0x5C00] = if self.in_frame { val } else { 0 },
+                2 => self.exram[addr as usize - 0x5C00] = val,
+                _ => {},
+            },
+            0x6000..=0xFFFF if self.prg_ram_writable() => {
+                let (rom, bank) = self.prg_target(addr);
+                if !rom && !self.prg_ram.is_empty() {
+                    let i = banked(&self.prg_ram, bank, 0x2000, addr);
+                    self.prg_ram[i] = val;
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        // a pattern fetch breaks up a run of nametable reads, so the two garbage reads of each sprite
+        // are not taken for the end of the line
+        self.last_nametable_addr = 0;
+        self.nametable_repeats = 0;
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let i = self.chr_index(addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn nametable_read(&mut self, addr:u16) -> Option<u8> {
+        self.track_nametable(addr);
+        let offset = addr as usize & 0x03FF;
+        let attribute = offset >= 0x03C0;
+        let background = self.in_frame && !self.sprite_phase();
+
+        if background && !attribute {
+            self.ext_tile = self.exram[offset];
+            self.update_split();
+            if self.split_tile {
+                return Some(self.exram[(self.split_y / 8) * 32 + self.split_column]);
+            }
+        }
+        if background && attribute {
+            // the PPU picks a quadrant from its own scroll, so hand back the palette in all four
+            if self.split_tile {
+                let (column, y) = (self.split_column, self.split_y);
+                let shift = ((y >> 4) & 1) * 4 + ((column >> 1) & 1) * 2;
+                let palette = (self.exram[0x3C0 + (y / 32) * 8 + column / 4] >> shift) & 0x03;
+                return Some(palette * 0x55);
+            }
+            if self.exram_mode == 1 {
+                return Some((self.ext_tile >> 6) * 0x55);
+            }
+        }
+
+        match (self.nametables >> (((addr >> 10) & 0x03) * 2)) & 0x03 {
+            2 if self.exram_mode <= 1 => Some(self.exram[offset]),
+            2 => Some(0),
+            3 if attribute => Some(self.fill_attribute * 0x55),
+            3 => Some(self.fill_tile),
+            _ => None,
+        }
+    }
+
+    fn nametable_write(&mut self, addr:u16, val:u8) -> bool {
+        match (self.nametables >> (((addr >> 10) & 0x03) * 2)) & 0x03 {
+            2 => {
+                if self.exram_mode <= 1 {
+                    self.exram[addr as usize & 0x03FF] = val;
+                }
+                true
+            },
+            3 => true,
+            _ => false,
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        let page = |quadrant:u8| (self.nametables >> (quadrant * 2)) & 0x01;
+        Mirroring::Mapped([page(0), page(1), page(2), page(3)])
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending && self.irq_enabled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cartridge::parse_rom;
+
+    fn mmc5() -> Mmc5 {
+        let mut image = b"NES\x1A\x01\x01\x50".to_vec();
+        image.resize(16 + 0x4000 + 0x2000, 0);
+        Mmc5::new(parse_rom(&image).unwrap())
+    }
+
+    // Tile, attribute, then the two pattern bytes
+    fn fetch_tile(mmc5:&mut Mmc5, addr:u16) {
+        mmc5.nametable_read(addr);
+        mmc5.nametable_read(0x23C0 | ((addr >> 4) & 0x38) | ((addr >> 2) & 0x07));
+        mmc5.ppu_read(0x0000);
+        mmc5.ppu_read(0x0008);
+    }
+
+    // The PPU fetches of dots 1 - 340 on a line of nametable row
+    fn fetch_line(mmc5:&mut Mmc5, row:u16) {
+        let base = 0x2000 | (row << 5);
+        for column in 2..34 {
+            fetch_tile(mmc5, base | (column & 0x1F));
+        }
+        // dots 257 - 320, two garbage nametable reads of the same address for each sprite
+        for _ in 0..8 {
+            mmc5.nametable_read(base);
+            mmc5.nametable_read(base);
+            mmc5.ppu_read(0x1FF0);
+            mmc5.ppu_read(0x1FF8);
+        }
+        // dots 321 - 340, the first two tiles of the next line then two reads of the third
+        let next = base + 0x20;
+        fetch_tile(mmc5, next);
+        fetch_tile(mmc5, next | 1);
+        mmc5.nametable_read(next | 2);
+        mmc5.nametable_read(next | 2);
+    }
+
+    #[test]
+    fn counts_one_scanline_per_line_of_fetches() {
+        let mut mmc5 = mmc5();
+        mmc5.cpu_write(0x5203, 3);
+        mmc5.cpu_write(0x5204, 0x80);
+        // end of the pre-render line
+        mmc5.nametable_read(0x2002);
+        mmc5.nametable_read(0x2002);
+        for row in 0..4 {
+            fetch_line(&mut mmc5, row);
+            assert!(mmc5.in_frame);
+            assert_eq!(mmc5.scanline, row as u8);
+            assert_eq!(mmc5.irq(), row == 3);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// Cartridge mappers
+// The board logic between the cartridge connector and the ROM/RAM chips
+
+mod axrom;
+mod cnrom;
+mod mmc1;
+mod mmc2;
+mod mmc3;
+mod mmc5;
+mod nrom;
+mod uxrom;
+mod vrc;
+mod vrc6;
+mod vrc7;
+
+use std::mem;
+
+use cartridge::{Cartridge, Mirroring, RomError};
+
+pub use self::mmc3::IrqBehaviour as Mmc3IrqBehaviour;
+
+// Mapper numbers with an implementation below
+pub const SUPPORTED_MAPPERS: &[u16] = &[0, 1, 2, 3, 4, 5, 7, 9, 10, 21, 22, 23, 24, 25, 26, 85];
+
+// Everything the console sees of a cartridge
+// The cpu side covers $4020 - $FFFF, the ppu side the pattern tables at $0000 - $1FFF
+// Nametables normally live in the console VRAM, arranged by mirroring, but a board may take them over
+pub trait Mapper {
+    // Reads from the cpu address space without side effects
+    // None when nothing on the board drives the data bus
+    fn cpu_peek(&self, addr:u16) -> Option<u8>;
+
+    // Reads from the cpu address space, for boards where reading has side effects
+    fn cpu_read(&mut self, addr:u16) -> Option<u8> {
+        self.cpu_peek(addr)
+    }
+
+    // Also sees writes to the PPU registers at $2000 - $3FFF, which some boards snoop
+    fn cpu_write(&mut self, addr:u16, val:u8);
+
+    // Called before every cpu bus cycle, like the R/W line seen on the cartridge connector
+    fn cpu_access(&mut self, _write:bool) {}
+
+    // Advances anything on the board clocked by M2, once per elapsed cpu cycle
+    fn clock(&mut self, _cycles:u32) {}
+
+    fn ppu_read(&mut self, addr:u16) -> u8;
+
+    fn ppu_write(&mut self, addr:u16, val:u8);
+
+    // Nametable read at $2000 - $2FFF, None to use the console VRAM
+    fn nametable_read(&mut self, _addr:u16) -> Option<u8> {
+        None
+    }
+
+    // Nametable write at $2000 - $2FFF, returns true if the board took it instead of the console VRAM
+    fn nametable_write(&mut self, _addr:u16, _val:u8) -> bool {
+        false
+    }
+
+    // PRG-RAM at $6000 - $7FFF, empty if the board has none
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    // Current nametable arrangement
+    fn mirroring(&self) -> Mirroring;
+
+    // Level of the cartridge IRQ output (true = asserted)
+    fn irq(&self) -> bool {
+        false
+    }
+}
+
+// Board details the header cannot tell apart, left to the user
+#[derive(Clone, Copy, Default, Debug)]
+pub struct MapperOptions {
+    pub mmc3_irq: Option<Mmc3IrqBehaviour>, // None to go by the header
+}
+
+// Builds the mapper the cartridge header asks for, taking ownership of its ROM
+pub fn new(cart:Cartridge) -> Result<Box<dyn Mapper>, RomError> {
+    with_options(cart, &MapperOptions::default())
+}
+
+// Builds the mapper with some of the board details chosen by the user
+pub fn with_options(cart:Cartridge, options:&MapperOptions) -> Result<Box<dyn Mapper>, RomError> {
+    match cart.mapper {
+        0 => Ok(Box::new(nrom::Nrom::new(cart))),
+        1 => Ok(Box::new(mmc1::Mmc1::new(cart))),
+        2 => Ok(Box::new(uxrom::Uxrom::new(cart))),
+        3 => Ok(Box::new(cnrom::Cnrom::new(cart))),
+        4 => Ok(Box::new(mmc3::Mmc3::new(cart, options))),
+        5 => Ok(Box::new(mmc5::Mmc5::new(cart))),
+        7 => Ok(Box::new(axrom::Axrom::new(cart))),
+        9 | 10 => Ok(Box::new(mmc2::Mmc2::new(cart))),
+        21 | 22 | 23 | 25 => Ok(Box::new(vrc::Vrc::new(cart))),
+        24 | 26 => Ok(Box::new(vrc6::Vrc6::new(cart))),
+        85 => Ok(Box::new(vrc7::Vrc7::new(cart))),
+        number => Err(RomError::UnsupportedMapper(number)),
+    }
+}
+
+// Allocates the PRG-RAM at $6000 - $7FFF, with the trainer (if any) at $7000
+fn prg_ram(cart:&Cartridge) -> Vec<u8> {
+    let mut size = cart.prg_ram_size + cart.prg_nvram_size;
+    if cart.trainer.is_some() { size = size.max(0x2000); }
+    let mut ram = vec![0u8; size];
+    if let Some(ref trainer) = cart.trainer {
+        ram[0x1000..0x1000 + trainer.len()].copy_from_slice(trainer);
+    }
+    ram
+}
+
+// For boards without PRG-RAM, 8kB to hold the trainer at $7000 when there is one, otherwise nothing
+fn trainer_ram(cart:&Cartridge) -> Vec<u8> {
+    if cart.trainer.is_some() { prg_ram(cart) } else { Vec::new() }
+}
+
+// CHR-ROM, or CHR-RAM when the board has none
+fn chr_memory(cart:&mut Cartridge) -> (Vec<u8>, bool) {
+    if cart.chr_rom.is_empty() {
+        (vec![0u8; (cart.chr_ram_size + cart.chr_nvram_size).max(0x2000)], true)
+    }
+    else {
+        (mem::take(&mut cart.chr_rom), false)
+    }
+}
+
+// Nametable arrangement fixed by the board, a four-screen board overrides the solder pad
+fn board_mirroring(cart:&Cartridge) -> Mirroring {
+    if cart.four_screen { Mirroring::FourScreen } else { cart.mirroring }
+}
+
+// Index into memory made of equally sized banks, wrapping bank numbers past the end like the unconnected address lines do
+fn banked(memory:&[u8], bank:usize, bank_size:usize, addr:u16) -> usize {
+    (bank * bank_size + (addr as usize % bank_size)) % memory.len()
+}
+
+// Whether writes to ROM are ANDed with the byte the ROM itself puts on the bus
+// NES 2.0 submapper 1 says the board has none, 2 says it does, otherwise go with what most boards did
+fn bus_conflicts(cart:&Cartridge, default:bool) -> bool {
+    match cart.submapper {
+        1 => false,
+        2 => true,
+        _ => default,
+    }
+}
@@ -0,0 +1,66 @@
+// NROM (mapper 0)
+// No bank switching, 16kB or 32kB of PRG-ROM and 8kB of CHR
+// Family Basic boards add PRG-RAM at $6000
+
+use cartridge::{Cartridge, Mirroring};
+use super::{board_mirroring, chr_memory, prg_ram, Mapper};
+
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(mut cart:Cartridge) -> Nrom {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        Nrom {
+            prg_ram: prg_ram(&cart),
+            mirroring: board_mirroring(&cart),
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if !self.prg_ram.is_empty() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            // a single 16kB bank (NROM-128) is mirrored into $C000 so the vectors are still found at the top
+            0x8000..=0xFFFF if !self.prg_rom.is_empty() => Some(self.prg_rom[(addr as usize - 0x8000) % self.prg_rom.len()]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if !self.prg_ram.is_empty() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            }
+        }
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.chr[addr as usize % self.chr.len()]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let len = self.chr.len();
+            self.chr[addr as usize % len] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,254 @@
+// Konami VRC2 and VRC4 (mappers 21, 22, 23 and 25)
+// Two switchable 8kB PRG banks, eight 1kB CHR banks written a nibble at a time
+// Each board wires different cpu address lines to the chip's register select pins
+// Also holds the cpu cycle IRQ counter shared by VRC4, VRC6 and VRC7
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{banked, chr_memory, prg_ram, Mapper};
+
+// IRQ counter clocked by M2
+// In scanline mode a prescaler divides the cpu clock by 113.667 to approximate a scanline
+pub struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pub pending: bool,
+}
+
+impl VrcIrq {
+    pub fn new() -> VrcIrq {
+        VrcIrq {
+            latch: 0,
+            counter: 0,
+            prescaler: 341,
+            enabled: false,
+            enable_after_ack: false,
+            cycle_mode: false,
+            pending: false,
+        }
+    }
+
+    pub fn set_latch(&mut self, val:u8) {
+        self.latch = val;
+    }
+
+    pub fn set_latch_low(&mut self, val:u8) {
+        self.latch = (self.latch & 0xF0) | (val & 0x0F);
+    }
+
+    pub fn set_latch_high(&mut self, val:u8) {
+        self.latch = (self.latch & 0x0F) | (val << 4);
+    }
+
+    // ...MEA: cycle mode, enable, enable after acknowledge
+    pub fn set_control(&mut self, val:u8) {
+        self.enable_after_ack = check_bit(val, 0);
+        self.enabled = check_bit(val, 1);
+        self.cycle_mode = check_bit(val, 2);
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = 341;
+        }
+        self.pending = false;
+    }
+
+    pub fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub fn clock(&mut self, cycles:u32) {
+        if !self.enabled {
+            return;
+        }
+        for _ in 0..cycles {
+            if self.cycle_mode {
+                self.clock_counter();
+            }
+            else {
+                self.prescaler -= 3;
+                if self.prescaler <= 0 {
+                    self.prescaler += 341;
+                    self.clock_counter();
+                }
+            }
+        }
+    }
+
+    // Counts up, firing and reloading on overflow
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        }
+        else {
+            self.counter += 1;
+        }
+    }
+}
+
+impl Default for VrcIrq {
+    fn default() -> VrcIrq {
+        VrcIrq::new()
+    }
+}
+
+pub struct Vrc {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    vrc2: bool, // no IRQ or PRG swap mode, and only two mirroring options
+    pins: [u16; 2], // cpu address lines on register select pins 0 and 1, ORed when the board is ambiguous
+    chr_shift: u8, // VRC2a drops the low bit of every CHR bank
+
+    prg_banks: [u8; 2],
+    chr_banks: [u16; 8],
+    control: u8, // ......SW: PRG swap mode, PRG-RAM enable
+    mirroring: Mirroring,
+    irq: VrcIrq,
+}
+
+impl Vrc {
+    pub fn new(mut cart:Cartridge) -> Vrc {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        // address lines for each variant, see the NES 2.0 submapper assignments
+        let (pins, vrc2) = match (cart.mapper, cart.submapper) {
+            (21, 1) => ([0x02, 0x04], false), // VRC4a
+            (21, 2) => ([0x40, 0x80], false), // VRC4c
+            (21, _) => ([0x42, 0x84], false),
+            (22, _) => ([0x02, 0x01], true), // VRC2a
+            (23, 1) => ([0x01, 0x02], false), // VRC4f
+            (23, 2) => ([0x04, 0x08], false), // VRC4e
+            (23, 3) => ([0x01, 0x02], true), // VRC2b
+            (23, _) => ([0x05, 0x0A], false),
+            (25, 1) => ([0x02, 0x01], false), // VRC4b
+            (25, 2) => ([0x08, 0x04], false), // VRC4d
+            (25, 3) => ([0x02, 0x01], true), // VRC2c
+            (_, _) => ([0x0A, 0x05], false),
+        };
+        Vrc {
+            prg_ram: prg_ram(&cart),
+            mirroring: cart.mirroring,
+            chr_shift: if cart.mapper == 22 { 1 } else { 0 },
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            vrc2,
+            pins,
+            prg_banks: [0, 1],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    // Register number 0-3 from the board specific address lines
+    fn register(&self, addr:u16) -> u16 {
+        (addr & 0xF000) | ((addr & self.pins[0] != 0) as u16) | (((addr & self.pins[1] != 0) as u16) << 1)
+    }
+
+    // 8kB PRG bank mapped at addr
+    fn prg_bank_at(&self, addr:u16) -> usize {
+        let last = self.prg_rom.len() / 0x2000;
+        let second_last = last.saturating_sub(2);
+        let swapped = !self.vrc2 && check_bit(self.control, 1);
+        match (addr >> 13) & 0x03 {
+            0 if swapped => second_last,
+            0 => self.prg_banks[0] as usize,
+            1 => self.prg_banks[1] as usize,
+            2 if swapped => self.prg_banks[0] as usize,
+            2 => second_last,
+            _ => last.saturating_sub(1),
+        }
+    }
+
+    fn chr_index(&self, addr:u16) -> usize {
+        let bank = self.chr_banks[(addr as usize >> 10) & 0x07] >> self.chr_shift;
+        banked(&self.chr, bank as usize, 0x400, addr)
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        // VRC2 boards always have it mapped, VRC4 needs the enable bit
+        !self.prg_ram.is_empty() && (self.vrc2 || check_bit(self.control, 0))
+    }
+}
+
+impl Mapper for Vrc {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xFFFF => Some(self.prg_rom[banked(&self.prg_rom, self.prg_bank_at(addr), 0x2000, addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            }
+            return;
+        }
+        let reg = self.register(addr);
+        match reg {
+            0x8000..=0x8003 => self.prg_banks[0] = val & 0x1F,
+            0x9000..=0x9003 if self.vrc2 => self.mirroring = if check_bit(val, 0) { Mirroring::Horizontal } else { Mirroring::Vertical },
+            0x9000 | 0x9001 => self.mirroring = match val & 0x03 {
+                0 => Mirroring::Vertical,
+                1 => Mirroring::Horizontal,
+                2 => Mirroring::SingleScreenLower,
+                _ => Mirroring::SingleScreenUpper,
+            },
+            0x9002 | 0x9003 => self.control = val,
+            0xA000..=0xA003 => self.prg_banks[1] = val & 0x1F,
+            0xB000..=0xEFFF => {
+                // two banks per register page, each written as a low and a high nibble
+                let bank = (((reg - 0xB000) >> 12) * 2 + ((reg >> 1) & 1)) as usize;
+                self.chr_banks[bank] = if reg & 1 == 0 {
+                    (self.chr_banks[bank] & 0x1F0) | (val & 0x0F) as u16
+                }
+                else {
+                    (self.chr_banks[bank] & 0x00F) | (((val & 0x1F) as u16) << 4)
+                };
+            },
+            0xF000 if !self.vrc2 => self.irq.set_latch_low(val),
+            0xF001 if !self.vrc2 => self.irq.set_latch_high(val),
+            0xF002 if !self.vrc2 => self.irq.set_control(val),
+            0xF003 if !self.vrc2 => self.irq.acknowledge(),
+            _ => {},
+        }
+    }
+
+    fn clock(&mut self, cycles:u32) {
+        self.irq.clock(cycles);
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.chr[self.chr_index(addr)]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let i = self.chr_index(addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+}
@@ -0,0 +1,125 @@
+// Konami VRC6 (mappers 24 and 26)
+// 16kB + 8kB switchable PRG, eight 1kB CHR banks and the VRC cycle IRQ
+// Mapper 26 swaps the two register select lines
+// The expansion audio registers at $9000 - $B002 are accepted and ignored
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{banked, chr_memory, prg_ram, Mapper};
+use super::vrc::VrcIrq;
+
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    swapped_pins: bool,
+
+    prg_16k: u8,
+    prg_8k: u8,
+    chr_banks: [u8; 8],
+    banking: u8, // $B003: PRG-RAM enable, CHR A10 rule, nametable source, mirroring, CHR mode
+    irq: VrcIrq,
+}
+
+impl Vrc6 {
+    pub fn new(mut cart:Cartridge) -> Vrc6 {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        Vrc6 {
+            prg_ram: prg_ram(&cart),
+            swapped_pins: cart.mapper == 26,
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            prg_16k: 0,
+            prg_8k: 0,
+            chr_banks: [0; 8],
+            banking: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    // 1kB CHR bank mapped at addr
+    // Mode 0 uses eight 1kB banks, mode 1 four 2kB banks, modes 2 and 3 mix the two across the pattern tables
+    fn chr_bank_at(&self, addr:u16) -> usize {
+        let slot = (addr as usize >> 10) & 0x07;
+        let two_k = |reg:usize| (self.chr_banks[reg] as usize) << 1 | (slot & 1);
+        match self.banking & 0x03 {
+            0 => self.chr_banks[slot] as usize,
+            1 => two_k(slot >> 1),
+            _ if slot < 4 => self.chr_banks[slot] as usize,
+            _ => two_k(4 + ((slot - 4) >> 1)),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        !self.prg_ram.is_empty() && check_bit(self.banking, 7)
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        let last = self.prg_rom.len() / 0x2000;
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xBFFF => Some(self.prg_rom[banked(&self.prg_rom, self.prg_16k as usize, 0x4000, addr)]),
+            0xC000..=0xDFFF => Some(self.prg_rom[banked(&self.prg_rom, self.prg_8k as usize, 0x2000, addr)]),
+            0xE000..=0xFFFF => Some(self.prg_rom[banked(&self.prg_rom, last.saturating_sub(1), 0x2000, addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            }
+            return;
+        }
+        let reg = if self.swapped_pins { (addr & 0xF000) | ((addr & 1) << 1) | ((addr >> 1) & 1) } else { addr & 0xF003 };
+        match reg {
+            0x8000..=0x8003 => self.prg_16k = val & 0x0F,
+            0xB003 => self.banking = val,
+            0xC000..=0xC003 => self.prg_8k = val & 0x1F,
+            0xD000..=0xD003 => self.chr_banks[(reg & 0x03) as usize] = val,
+            0xE000..=0xE003 => self.chr_banks[4 + (reg & 0x03) as usize] = val,
+            0xF000 => self.irq.set_latch(val),
+            0xF001 => self.irq.set_control(val),
+            0xF002 => self.irq.acknowledge(),
+            _ => {},
+        }
+    }
+
+    fn clock(&mut self, cycles:u32) {
+        self.irq.clock(cycles);
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.chr[banked(&self.chr, self.chr_bank_at(addr), 0x400, addr)]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let i = banked(&self.chr, self.chr_bank_at(addr), 0x400, addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking >> 2) & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+}
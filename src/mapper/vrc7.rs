@@ -0,0 +1,111 @@
+// Konami VRC7 (mapper 85)
+// Three switchable 8kB PRG banks, eight 1kB CHR banks and the VRC cycle IRQ
+// VRC7a selects registers with A4 ($x010), VRC7b with A3 ($x008), both are accepted
+// The FM audio registers at $9010 and $9030 are accepted and ignored
+
+use cartridge::{Cartridge, Mirroring};
+use util::check_bit;
+use super::{banked, chr_memory, prg_ram, Mapper};
+use super::vrc::VrcIrq;
+
+pub struct Vrc7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    control: u8, // RS....MM: PRG-RAM enable, silence audio, mirroring
+    irq: VrcIrq,
+}
+
+impl Vrc7 {
+    pub fn new(mut cart:Cartridge) -> Vrc7 {
+        let (chr, chr_is_ram) = chr_memory(&mut cart);
+        Vrc7 {
+            prg_ram: prg_ram(&cart),
+            prg_rom: cart.prg_rom,
+            chr,
+            chr_is_ram,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: 0,
+            irq: VrcIrq::new(),
+        }
+    }
+
+    fn prg_ram_enabled(&self) -> bool {
+        !self.prg_ram.is_empty() && check_bit(self.control, 7)
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_peek(&self, addr:u16) -> Option<u8> {
+        match addr {
+            0x6000..=0x7FFF if self.prg_ram_enabled() => Some(self.prg_ram[(addr as usize - 0x6000) % self.prg_ram.len()]),
+            0x8000..=0xDFFF => Some(self.prg_rom[banked(&self.prg_rom, self.prg_banks[(addr as usize - 0x8000) >> 13] as usize, 0x2000, addr)]),
+            0xE000..=0xFFFF => Some(self.prg_rom[banked(&self.prg_rom, (self.prg_rom.len() / 0x2000).saturating_sub(1), 0x2000, addr)]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr:u16, val:u8) {
+        if let 0x6000..=0x7FFF = addr {
+            if self.prg_ram_enabled() {
+                let len = self.prg_ram.len();
+                self.prg_ram[(addr as usize - 0x6000) % len] = val;
+            }
+            return;
+        }
+        // second register of each page, whichever line the board uses
+        let high = addr & 0x0018 != 0;
+        match (addr & 0xF000, high) {
+            (0x8000, false) => self.prg_banks[0] = val & 0x3F,
+            (0x8000, true) => self.prg_banks[1] = val & 0x3F,
+            // $9010 and $9030 belong to the audio chip
+            (0x9000, false) if addr & 0x0030 == 0 => self.prg_banks[2] = val & 0x3F,
+            (0xA000..=0xD000, _) => {
+                let bank = ((addr as usize - 0xA000) >> 12) * 2 + high as usize;
+                self.chr_banks[bank] = val;
+            },
+            (0xE000, false) => self.control = val,
+            (0xE000, true) => self.irq.set_latch(val),
+            (0xF000, false) => self.irq.set_control(val),
+            (0xF000, true) => self.irq.acknowledge(),
+            _ => {},
+        }
+    }
+
+    fn clock(&mut self, cycles:u32) {
+        self.irq.clock(cycles);
+    }
+
+    fn ppu_read(&mut self, addr:u16) -> u8 {
+        self.chr[banked(&self.chr, self.chr_banks[(addr as usize >> 10) & 0x07] as usize, 0x400, addr)]
+    }
+
+    fn ppu_write(&mut self, addr:u16, val:u8) {
+        if self.chr_is_ram {
+            let i = banked(&self.chr, self.chr_banks[(addr as usize >> 10) & 0x07] as usize, 0x400, addr);
+            self.chr[i] = val;
+        }
+    }
+
+    fn prg_ram(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0x03 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending
+    }
+}
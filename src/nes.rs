@@ -0,0 +1,200 @@
+// NES system
+// Ties the cpu, PPU and cartridge together and runs them in real time
+
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use bus::RamInit;
+use cartridge;
+use cartridge::{Cartridge, RomError};
+use cpu::{Cpu, CpuVariant};
+use mapper;
+use mapper::{MapperOptions, Mmc3IrqBehaviour};
+use save;
+
+// How often battery backed RAM is written out while running
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+pub struct NESEmulator {
+    pub cpu: Cpu,
+
+    // Clock Speed (MHz)
+    clock: f32,
+
+    // File Path
+    filepath: String,
+
+    // Battery save, only for cartridges with a battery
+    battery: bool,
+    save_path: Option<PathBuf>,
+    saved_ram: Vec<u8>, // PRG-RAM as of the last save, to skip writing when nothing changed
+
+    // Board details for the next cartridge loaded
+    mapper_options: MapperOptions,
+
+    // Print every instruction from run
+    trace: bool,
+
+    // Set from any thread to make run return
+    stop: Arc<AtomicBool>,
+}
+
+// implimentation
+impl NESEmulator {
+    // initializes registers
+    pub fn new(f: &str) -> NESEmulator {
+        NESEmulator::with_variant(f, CpuVariant::Ricoh2A03)
+    }
+
+    // initializes registers for a specific cpu variant
+    pub fn with_variant(f: &str, variant: CpuVariant) -> NESEmulator {
+        NESEmulator {
+            cpu: Cpu::new(variant),
+            clock: 1.79, // US-region
+            filepath: f.to_owned(),
+            battery: false,
+            save_path: None,
+            saved_ram: Vec::new(),
+            mapper_options: MapperOptions::default(),
+            trace: false,
+            stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Reads the ROM at the path given to new and inserts it
+    pub fn load_rom(&mut self) -> Result<(), RomError> {
+        let cart = cartridge::load_rom(&self.filepath)?;
+        self.insert_cartridge(cart)
+    }
+
+    // Plugs a parsed cartridge in, its battery save is left to load_save
+    pub fn insert_cartridge(&mut self, cart:Cartridge) -> Result<(), RomError> {
+        self.battery = cart.battery;
+        self.cpu.bus.insert_cartridge(mapper::with_options(cart, &self.mapper_options)?);
+        Ok(())
+    }
+
+    // Restores the PRG-RAM from the .sav file next to the ROM, returning false if there is no save yet
+    // Only once this succeeds does flush_save write anything, so a save that could not be read is never written over
+    pub fn load_save(&mut self) -> io::Result<bool> {
+        if !self.battery {
+            return Ok(false);
+        }
+        let path = save::save_path(&self.filepath);
+        let loaded = save::load(&path, self.cpu.bus.prg_ram())?;
+        self.saved_ram = self.cpu.bus.prg_ram().to_vec();
+        self.save_path = Some(path);
+        Ok(loaded)
+    }
+
+    // Writes battery backed RAM out if it changed since the last save
+    pub fn flush_save(&mut self) -> io::Result<()> {
+        let path = match self.save_path {
+            Some(ref path) => path.clone(),
+            None => return Ok(()),
+        };
+        if self.cpu.bus.prg_ram() == &self.saved_ram[..] {
+            return Ok(());
+        }
+        let ram = self.cpu.bus.prg_ram().to_vec();
+        save::store(&path, &ram)?;
+        self.saved_ram = ram;
+        Ok(())
+    }
+
+    // Picks the MMC3 IRQ revision for the next cartridge loaded, None to go by the header
+    pub fn set_mmc3_irq(&mut self, behaviour:Option<Mmc3IrqBehaviour>) {
+        self.mapper_options.mmc3_irq = behaviour;
+    }
+
+    // Sets the pattern the internal RAM is filled with at power on
+    pub fn set_ram_init(&mut self, ram_init:RamInit) {
+        self.cpu.bus.set_ram_init(ram_init);
+    }
+
+    pub fn power_on(&mut self) {
+        self.cpu.power_on();
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.bus.reset();
+        self.cpu.reset();
+    }
+
+    // Runs one cpu instruction, the cpu clocks the rest of the system along with it
+    pub fn step(&mut self) -> u32 {
+        self.cpu.tick()
+    }
+
+    // Runs until the PPU finishes the frame it is on, flat out and without tracing
+    // Returns false without running anything when there is no cartridge, as the PPU never finishes a frame then
+    pub fn run_frame(&mut self) -> bool {
+        if !self.cpu.bus.has_cartridge() {
+            return false;
+        }
+        let frame = self.cpu.bus.ppu().frame();
+        while self.cpu.bus.ppu().frame() == frame {
+            self.step();
+        }
+        true
+    }
+
+    // Picture as the PPU has drawn it so far, see ppu::Ppu::framebuffer
+    pub fn framebuffer(&self) -> &[u16] {
+        self.cpu.bus.ppu().framebuffer()
+    }
+
+    // Has run print each instruction before executing it, which slows it well below real time
+    pub fn set_trace(&mut self, trace:bool) {
+        self.trace = trace;
+    }
+
+    // Flag that stops run once set, can be handed to another thread or a signal handler
+    pub fn stop_handle(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+
+    // Runs in real time until stopped through stop_handle or the cpu halts
+    // The battery save is written every few seconds, a failed write is tried again next time,
+    // and once more on the way out with the result returned
+    pub fn run(&mut self) -> io::Result<()> {
+        let cycle_time = 1.0 / (self.clock as f64 * 1_000_000.0);
+        let start_time = Instant::now();
+        let start_cycles = self.cpu.cycles();
+        let mut last_save = Instant::now();
+        while !self.stop.load(Ordering::Relaxed) && !self.cpu.halted() {
+            if self.trace {
+                let cpu = &self.cpu;
+                println!("[0x{:0>4x}] sp: ${:0>2x}, a: #{:0>2x}, x: #{:0>2x}, y: #{:0>2x}, p: {:0>8b}, cyc: {}, op: {}", cpu.pc, cpu.sp, cpu.a, cpu.x, cpu.y, cpu.p, cpu.cycles(), cpu.disassemble(cpu.pc));
+            }
+            self.step();
+
+            // pace execution to the real clock speed
+            let target = Duration::from_secs_f64((self.cpu.cycles() - start_cycles) as f64 * cycle_time);
+            let elapsed = start_time.elapsed();
+            if target > elapsed {
+                thread::sleep(target - elapsed);
+            }
+
+            if last_save.elapsed() >= SAVE_INTERVAL {
+                // the RAM still differs from the save after a failure, so the next flush retries
+                let _ = self.flush_save();
+                last_save = Instant::now();
+            }
+        }
+
+        self.flush_save()
+    }
+}
+
+// Flush the battery save on the way out
+// Errors can't be reported from here, call flush_save first to see them
+impl Drop for NESEmulator {
+    fn drop(&mut self) {
+        let _ = self.flush_save();
+    }
+}
@@ -0,0 +1,19 @@
+// Frame dumps
+// Writes framebuffers out as binary PPM images, which most image tools read and which need no encoder
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ppu::{HEIGHT, WIDTH};
+use ppu::palette::Palette;
+
+// P6 header followed by the RGB of every pixel, row by row
+pub fn write_ppm(path:&Path, framebuffer:&[u16], palette:&Palette) -> io::Result<()> {
+    let mut data = format!("P6\n{} {}\n255\n", WIDTH, HEIGHT).into_bytes();
+    data.reserve(WIDTH * HEIGHT * 3);
+    for &pixel in framebuffer {
+        data.extend_from_slice(&palette.rgb(pixel));
+    }
+    fs::write(path, data)
+}
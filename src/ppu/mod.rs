@@ -0,0 +1,540 @@
+// Picture processing unit (2C02)
+// Steps one dot at a time, making the same memory fetches in the same order as the real chip,
+// so boards that watch the PPU address bus (MMC3, MMC5...) see what they expect
+// Notes:
+// + 341 dots per scanline, 262 scanlines per frame
+// + scanlines 0-239 are visible, 241-260 are vblank and 261 is the pre-render line
+// + three dots per cpu cycle on NTSC
+// + 256x240 picture, each pixel a 6-bit colour with the PPUMASK emphasis bits above it, see palette.rs
+// + up to 8 sprites a line, from the 64 in OAM
+
+pub mod palette;
+mod sprites;
+
+use cartridge::Mirroring;
+use mapper::Mapper;
+use util::check_bit;
+
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+const DOTS: u16 = 341;
+const VBLANK_LINE: u16 = 241;
+const PRE_RENDER_LINE: u16 = 261;
+
+// PPUCTRL ($2000) bit positions
+const CTRL_INCREMENT: usize = 2;
+const CTRL_SPRITE_TABLE: usize = 3;
+const CTRL_BG_TABLE: usize = 4;
+const CTRL_SPRITE_SIZE: usize = 5;
+const CTRL_NMI: usize = 7;
+
+// PPUMASK ($2001) bit positions
+const MASK_GREYSCALE: usize = 0;
+const MASK_BG_LEFT: usize = 1;
+const MASK_SPRITES_LEFT: usize = 2;
+const MASK_BG: usize = 3;
+const MASK_SPRITES: usize = 4;
+
+// PPUSTATUS ($2002) bit positions
+const STATUS_SPRITE_OVERFLOW: usize = 5;
+const STATUS_SPRITE_ZERO: usize = 6;
+const STATUS_VBLANK: usize = 7;
+
+pub struct Ppu {
+    // Registers
+    ctrl: u8, // VPHBSINN: NMI enable, master/slave, sprite size, BG table, sprite table, increment, nametable
+    mask: u8, // BGRsbMmG: emphasis, show sprites, show BG, sprites and BG in the left 8 pixels, greyscale
+    status: u8, // VSO.....: vblank, sprite zero hit, sprite overflow
+    io_latch: u8, // last value put on the register data bus, read back from the write-only registers
+    read_buffer: u8, // $2007 reads outside the palette return the byte fetched by the previous read
+
+    // Internal scroll registers
+    v: u16, // current VRAM address, .yyyNNYYYYYXXXXX: fine y, nametable, coarse y, coarse x
+    t: u16, // temporary VRAM address, the top left corner of the screen
+    x: u8, // fine x scroll
+    w: bool, // first/second write toggle shared by $2005 and $2006
+
+    // Memory
+    vram: [u8; 0x1000], // 2kB of nametable RAM, the upper half stands in for the extra RAM of four-screen boards
+    palette: [u8; 0x20],
+    oam: [u8; 0x100], // 64 sprites of 4 bytes: Y, tile, attributes, X
+    oam_addr: u8,
+    secondary_oam: [u8; 0x20], // the sprites found for the next line
+
+    // Position
+    scanline: u16,
+    dot: u16,
+    frame: u64, // frames completed, counted at the start of vblank
+
+    // Background pipeline
+    // The latches hold the tile being fetched, the shift registers the two tiles being drawn
+    tile: u8,
+    attribute: u8,
+    pattern_lo: u8,
+    pattern_hi: u8,
+    bg_pattern_lo: u16,
+    bg_pattern_hi: u16,
+    bg_attribute_lo: u16,
+    bg_attribute_hi: u16,
+
+    // Sprite evaluation, n is the sprite in OAM and m the byte within it
+    eval_n: usize,
+    eval_m: usize,
+    eval_count: usize, // sprites copied to secondary OAM
+    eval_done: bool,
+    eval_sprite_zero: bool, // sprite 0 is among them
+    eval_line: Option<u16>, // line the search last started on, as rendering can be switched off part way through
+
+    // Sprites being drawn on this line
+    sprite_count: usize,
+    sprite_zero_line: bool, // slot 0 holds sprite 0
+    sprite_pattern_lo: [u8; 8],
+    sprite_pattern_hi: [u8; 8],
+    sprite_attribute: [u8; 8],
+    sprite_x: [u8; 8],
+
+    framebuffer: Vec<u16>,
+}
+
+impl Ppu {
+    pub fn new() -> Ppu {
+        Ppu {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            io_latch: 0,
+            read_buffer: 0,
+            v: 0,
+            t: 0,
+            x: 0,
+            w: false,
+            vram: [0u8; 0x1000],
+            palette: [0u8; 0x20],
+            oam: [0u8; 0x100],
+            oam_addr: 0,
+            secondary_oam: [0xFFu8; 0x20],
+            scanline: 0,
+            dot: 0,
+            frame: 0,
+            tile: 0,
+            attribute: 0,
+            pattern_lo: 0,
+            pattern_hi: 0,
+            bg_pattern_lo: 0,
+            bg_pattern_hi: 0,
+            bg_attribute_lo: 0,
+            bg_attribute_hi: 0,
+            eval_n: 0,
+            eval_m: 0,
+            eval_count: 0,
+            eval_done: false,
+            eval_sprite_zero: false,
+            eval_line: None,
+            sprite_count: 0,
+            sprite_zero_line: false,
+            sprite_pattern_lo: [0u8; 8],
+            sprite_pattern_hi: [0u8; 8],
+            sprite_attribute: [0u8; 8],
+            sprite_x: [0u8; 8],
+            framebuffer: vec![0u16; WIDTH * HEIGHT],
+        }
+    }
+
+    // Power on
+    // Clears the memories and starts at the top of a frame
+    pub fn power_on(&mut self) {
+        *self = Ppu::new();
+    }
+
+    // Reset
+    // The reset line clears the control registers and the write toggle, memory and scroll are left alone
+    pub fn reset(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.w = false;
+        self.read_buffer = 0;
+    }
+
+    // Frame being drawn, row by row, one palette::Palette index per pixel
+    pub fn framebuffer(&self) -> &[u16] {
+        &self.framebuffer
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    // Level of the NMI output (true = asserted), held for as long as vblank is flagged and NMIs are enabled
+    pub fn nmi(&self) -> bool {
+        check_bit(self.status, STATUS_VBLANK) && check_bit(self.ctrl, CTRL_NMI)
+    }
+
+    // Reads a register at $2000 - $3FFF, with the side effects the read has
+    pub fn read_register(&mut self, addr:u16, mapper:&mut dyn Mapper) -> u8 {
+        let val = match addr & 0x0007 {
+            // PPUSTATUS, the low bits are whatever was last on the bus
+            2 => {
+                let val = (self.status & 0xE0) | (self.io_latch & 0x1F);
+                self.status &= !(1 << STATUS_VBLANK);
+                self.w = false;
+                val
+            },
+            // OAMDATA, reads $FF while secondary OAM is being cleared
+            4 if self.rendering() && self.scanline < HEIGHT as u16 && (1..=64).contains(&self.dot) => 0xFF,
+            4 => self.oam[self.oam_addr as usize],
+            // PPUDATA
+            7 => {
+                let addr = self.v & 0x3FFF;
+                let val = if addr >= 0x3F00 {
+                    // palette reads are not buffered, the buffer gets the nametable byte underneath instead
+                    self.read_buffer = self.read(mapper, addr - 0x1000);
+                    self.palette_colour(addr) | (self.io_latch & 0xC0)
+                }
+                else {
+                    let val = self.read_buffer;
+                    self.read_buffer = self.read(mapper, addr);
+                    val
+                };
+                self.increment_v();
+                val
+            },
+            _ => self.io_latch,
+        };
+        self.io_latch = val;
+        val
+    }
+
+    // Reads a register without side effects, for debugging and tracing
+    pub fn peek_register(&self, addr:u16) -> u8 {
+        match addr & 0x0007 {
+            2 => (self.status & 0xE0) | (self.io_latch & 0x1F),
+            4 => self.oam[self.oam_addr as usize],
+            7 if self.v & 0x3F00 == 0x3F00 => self.palette_colour(self.v) | (self.io_latch & 0xC0),
+            7 => self.read_buffer,
+            _ => self.io_latch,
+        }
+    }
+
+    // Writes a register at $2000 - $3FFF
+    pub fn write_register(&mut self, addr:u16, val:u8, mapper:&mut dyn Mapper) {
+        self.io_latch = val;
+        match addr & 0x0007 {
+            // PPUCTRL, the nametable bits go to t
+            0 => {
+                self.ctrl = val;
+                self.t = (self.t & !0x0C00) | ((val as u16 & 0x03) << 10);
+            },
+            // PPUMASK
+            1 => self.mask = val,
+            // OAMADDR
+            3 => self.oam_addr = val,
+            // OAMDATA
+            4 => self.write_oam(val),
+            // PPUSCROLL, x then y
+            5 => {
+                if !self.w {
+                    self.t = (self.t & !0x001F) | (val as u16 >> 3);
+                    self.x = val & 0x07;
+                }
+                else {
+                    self.t = (self.t & !0x73E0) | ((val as u16 & 0x07) << 12) | ((val as u16 & 0xF8) << 2);
+                }
+                self.w = !self.w;
+            },
+            // PPUADDR, high byte then low byte, v only changes on the second write
+            6 => {
+                if !self.w {
+                    self.t = (self.t & 0x00FF) | ((val as u16 & 0x3F) << 8);
+                }
+                else {
+                    self.t = (self.t & 0xFF00) | val as u16;
+                    self.v = self.t;
+                }
+                self.w = !self.w;
+            },
+            // PPUDATA
+            7 => {
+                self.write(mapper, self.v, val);
+                self.increment_v();
+            },
+            _ => {},
+        }
+    }
+
+    // Writes the byte at OAMADDR and moves on to the next
+    // While rendering the write is dropped and OAMADDR jumps to the next sprite instead
+    pub fn write_oam(&mut self, val:u8) {
+        if self.rendering() && (self.scanline < HEIGHT as u16 || self.scanline == PRE_RENDER_LINE) {
+            self.oam_addr = self.oam_addr.wrapping_add(4);
+            return;
+        }
+        // bits 2-4 of the attribute byte do not exist
+        let val = if self.oam_addr & 0x03 == 2 { val & 0xE3 } else { val };
+        self.oam[self.oam_addr as usize] = val;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    // Advances one dot
+    pub fn step(&mut self, mapper:&mut dyn Mapper) {
+        let rendering = self.rendering();
+        if rendering && (self.scanline < HEIGHT as u16 || self.scanline == PRE_RENDER_LINE) {
+            self.fetch(mapper);
+        }
+        if self.scanline < HEIGHT as u16 && (1..=WIDTH as u16).contains(&self.dot) {
+            self.render_pixel();
+        }
+
+        if self.scanline == VBLANK_LINE && self.dot == 1 {
+            self.status |= 1 << STATUS_VBLANK;
+            self.frame += 1;
+            // a search left unfinished by rendering being switched off belongs to this frame only
+            self.eval_line = None;
+        }
+        if self.scanline == PRE_RENDER_LINE && self.dot == 1 {
+            self.status = 0;
+        }
+
+        // odd frames skip the last dot of the pre-render line when rendering
+        if self.scanline == PRE_RENDER_LINE && self.dot == DOTS - 2 && rendering && self.frame % 2 == 1 {
+            self.dot += 1;
+        }
+        self.dot += 1;
+        if self.dot == DOTS {
+            self.dot = 0;
+            self.scanline = if self.scanline == PRE_RENDER_LINE { 0 } else { self.scanline + 1 };
+        }
+    }
+
+    fn rendering(&self) -> bool {
+        check_bit(self.mask, MASK_BG) || check_bit(self.mask, MASK_SPRITES)
+    }
+
+    // Memory fetches of the visible and pre-render lines
+    // Each fetch takes two dots, four fetches make up a tile: nametable, attribute, pattern low, pattern high
+    fn fetch(&mut self, mapper:&mut dyn Mapper) {
+        let dot = self.dot;
+        if (2..=257).contains(&dot) || (322..=337).contains(&dot) {
+            self.shift_background();
+            if dot % 8 == 1 {
+                self.reload_background();
+            }
+        }
+
+        // 32 tiles for this line, then the first two of the next
+        if (1..=256).contains(&dot) || (321..=336).contains(&dot) {
+            match dot % 8 {
+                1 => self.tile = self.read(mapper, 0x2000 | (self.v & 0x0FFF)),
+                3 => {
+                    let v = self.v;
+                    let addr = 0x23C0 | (v & 0x0C00) | ((v >> 4) & 0x38) | ((v >> 2) & 0x07);
+                    // each attribute byte covers 4x4 tiles, two bits per 2x2 quadrant
+                    let shift = ((v >> 4) & 0x04) | (v & 0x02);
+                    self.attribute = (self.read(mapper, addr) >> shift) & 0x03;
+                },
+                5 => self.pattern_lo = self.read(mapper, self.bg_pattern_address()),
+                7 => self.pattern_hi = self.read(mapper, self.bg_pattern_address() + 8),
+                0 => self.increment_x(),
+                _ => {},
+            }
+        }
+
+        match dot {
+            256 => self.increment_y(),
+            257 => self.copy_x(),
+            280..=304 if self.scanline == PRE_RENDER_LINE => self.copy_y(),
+            _ => {},
+        }
+
+        if self.scanline < HEIGHT as u16 {
+            self.evaluate_sprites();
+        }
+        if (257..=320).contains(&dot) {
+            self.fetch_sprite(mapper);
+        }
+
+        // two unused nametable fetches end the line
+        if dot == 337 || dot == 339 {
+            self.read(mapper, 0x2000 | (self.v & 0x0FFF));
+        }
+    }
+
+    fn bg_pattern_address(&self) -> u16 {
+        let table = if check_bit(self.ctrl, CTRL_BG_TABLE) { 0x1000 } else { 0 };
+        table | ((self.tile as u16) << 4) | (self.v >> 12)
+    }
+
+    fn shift_background(&mut self) {
+        self.bg_pattern_lo <<= 1;
+        self.bg_pattern_hi <<= 1;
+        self.bg_attribute_lo <<= 1;
+        self.bg_attribute_hi <<= 1;
+    }
+
+    // Moves the fetched tile into the low half of the shift registers
+    fn reload_background(&mut self) {
+        let spread = |bit:bool| if bit { 0xFF } else { 0x00 };
+        self.bg_pattern_lo = (self.bg_pattern_lo & 0xFF00) | self.pattern_lo as u16;
+        self.bg_pattern_hi = (self.bg_pattern_hi & 0xFF00) | self.pattern_hi as u16;
+        self.bg_attribute_lo = (self.bg_attribute_lo & 0xFF00) | spread(check_bit(self.attribute, 0));
+        self.bg_attribute_hi = (self.bg_attribute_hi & 0xFF00) | spread(check_bit(self.attribute, 1));
+    }
+
+    fn render_pixel(&mut self) {
+        let x = self.dot as usize - 1;
+        let colour = if self.rendering() {
+            let mut bg = 0;
+            if check_bit(self.mask, MASK_BG) && (x >= 8 || check_bit(self.mask, MASK_BG_LEFT)) {
+                let bit = 15 - self.x as u16;
+                let pixel = (((self.bg_pattern_hi >> bit) & 1) << 1) | ((self.bg_pattern_lo >> bit) & 1);
+                let palette = (((self.bg_attribute_hi >> bit) & 1) << 1) | ((self.bg_attribute_lo >> bit) & 1);
+                if pixel != 0 {
+                    bg = (palette << 2) | pixel;
+                }
+            }
+            let mut index = bg;
+            if check_bit(self.mask, MASK_SPRITES) && (x >= 8 || check_bit(self.mask, MASK_SPRITES_LEFT)) {
+                if let Some(sprite) = self.sprite_pixel(x) {
+                    // sprite zero hit needs both layers opaque, and never happens at x = 255
+                    if sprite.sprite_zero && bg != 0 && x != 255 {
+                        self.status |= 1 << STATUS_SPRITE_ZERO;
+                    }
+                    if bg == 0 || !sprite.behind_bg {
+                        index = sprite.index as u16;
+                    }
+                }
+            }
+            self.palette_colour(index)
+        }
+        else if self.v & 0x3F00 == 0x3F00 {
+            // with rendering off the backdrop comes from wherever v points, if that is the palette
+            self.palette_colour(self.v)
+        }
+        else {
+            self.palette_colour(0)
+        };
+        let emphasis = (self.mask as u16 & 0xE0) << 1;
+        self.framebuffer[self.scanline as usize * WIDTH + x] = colour as u16 | emphasis;
+    }
+
+    // Colour from palette RAM as it leaves the PPU, greyscale keeps only the brightness
+    fn palette_colour(&self, addr:u16) -> u8 {
+        let colour = self.palette[palette_index(addr)];
+        if check_bit(self.mask, MASK_GREYSCALE) { colour & 0x30 } else { colour & 0x3F }
+    }
+
+    // Moves v on after a $2007 access
+    // While rendering the access clashes with the fetch logic, which bumps coarse x and y instead
+    fn increment_v(&mut self) {
+        if self.rendering() && (self.scanline < HEIGHT as u16 || self.scanline == PRE_RENDER_LINE) {
+            self.increment_x();
+            self.increment_y();
+        }
+        else {
+            let step = if check_bit(self.ctrl, CTRL_INCREMENT) { 32 } else { 1 };
+            self.v = (self.v + step) & 0x7FFF;
+        }
+    }
+
+    // Next tile across, wrapping into the horizontally adjacent nametable
+    fn increment_x(&mut self) {
+        if self.v & 0x001F == 31 {
+            self.v = (self.v & !0x001F) ^ 0x0400;
+        }
+        else {
+            self.v += 1;
+        }
+    }
+
+    // Next pixel row down, wrapping into the vertically adjacent nametable after row 29
+    // Coarse y of 30 and 31 points into the attribute table and wraps without switching nametable
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let coarse_y = match (self.v & 0x03E0) >> 5 {
+            29 => {
+                self.v ^= 0x0800;
+                0
+            },
+            31 => 0,
+            y => y + 1,
+        };
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    fn copy_x(&mut self) {
+        self.v = (self.v & !0x041F) | (self.t & 0x041F);
+    }
+
+    fn copy_y(&mut self) {
+        self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+    }
+
+    // PPU address space
+    // $0000 - $1FFF pattern tables on the cartridge, $2000 - $3EFF nametables, $3F00 - $3FFF palette
+    fn read(&mut self, mapper:&mut dyn Mapper, addr:u16) -> u8 {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => mapper.ppu_read(addr),
+            0x2000..=0x3EFF => {
+                let addr = 0x2000 | (addr & 0x0FFF);
+                match mapper.nametable_read(addr) {
+                    Some(val) => val,
+                    None => self.vram[vram_index(mapper.mirroring(), addr)],
+                }
+            },
+            _ => self.palette[palette_index(addr)],
+        }
+    }
+
+    fn write(&mut self, mapper:&mut dyn Mapper, addr:u16, val:u8) {
+        let addr = addr & 0x3FFF;
+        match addr {
+            0x0000..=0x1FFF => mapper.ppu_write(addr, val),
+            0x2000..=0x3EFF => {
+                let addr = 0x2000 | (addr & 0x0FFF);
+                if !mapper.nametable_write(addr, val) {
+                    self.vram[vram_index(mapper.mirroring(), addr)] = val;
+                }
+            },
+            _ => self.palette[palette_index(addr)] = val & 0x3F,
+        }
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Ppu {
+        Ppu::new()
+    }
+}
+
+// Where a nametable address lands in VRAM, given the cartridge's arrangement of the four nametables
+fn vram_index(mirroring:Mirroring, addr:u16) -> usize {
+    let table = (addr as usize >> 10) & 0x03;
+    let page = match mirroring {
+        Mirroring::Horizontal => table >> 1,
+        Mirroring::Vertical => table & 0x01,
+        Mirroring::SingleScreenLower => 0,
+        Mirroring::SingleScreenUpper => 1,
+        Mirroring::FourScreen => table,
+        Mirroring::Mapped(pages) => pages[table] as usize,
+    };
+    page * 0x400 + (addr as usize & 0x03FF)
+}
+
+// Palette RAM index, the backdrop entries of the sprite palettes ($3F10, $3F14...) are shared with the background ones
+fn palette_index(addr:u16) -> usize {
+    let index = addr as usize & 0x1F;
+    if index & 0x13 == 0x10 { index & 0x0F } else { index }
+}
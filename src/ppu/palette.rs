@@ -0,0 +1,121 @@
+// Master palette
+// The PPU puts out a 6-bit colour (4 bits of hue, 2 of brightness) plus the 3 emphasis bits of PPUMASK
+// rather than RGB, a palette says what each of those 512 combinations looks like
+// Palettes load from the usual .pal files: 64 colours (192 bytes), or 512 colours (1536 bytes) covering every emphasis
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+
+// How much emphasising one colour darkens the other two
+const EMPHASIS_ATTENUATION: f32 = 0.816;
+
+// Reasons a palette file can be rejected
+#[derive(Debug)]
+pub enum PaletteError {
+    Io(io::Error), // the file could not be opened or read
+    BadSize(usize), // neither 192 nor 1536 bytes long
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PaletteError::Io(ref err) => write!(f, "could not read palette: {}", err),
+            PaletteError::BadSize(size) => write!(f, "palette is {} bytes, expected 192 or 1536", size),
+        }
+    }
+}
+
+impl Error for PaletteError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PaletteError::Io(ref err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PaletteError {
+    fn from(err: io::Error) -> PaletteError {
+        PaletteError::Io(err)
+    }
+}
+
+pub struct Palette {
+    colours: Vec<[u8; 3]>, // 64 colours for each of the 8 emphasis combinations
+}
+
+impl Palette {
+    // The built-in NTSC palette
+    pub fn ntsc() -> Palette {
+        Palette::with_emphasis(&NTSC)
+    }
+
+    // Reads a .pal file
+    pub fn load(path:&str) -> Result<Palette, PaletteError> {
+        Palette::from_bytes(&fs::read(path)?)
+    }
+
+    // RGB triplets, either the 64 base colours or all 512 with emphasis
+    pub fn from_bytes(data:&[u8]) -> Result<Palette, PaletteError> {
+        if data.len() != 192 && data.len() != 1536 {
+            return Err(PaletteError::BadSize(data.len()));
+        }
+        let colours:Vec<[u8; 3]> = data.chunks(3).map(|rgb| [rgb[0], rgb[1], rgb[2]]).collect();
+        if colours.len() == 64 { Ok(Palette::with_emphasis(&colours)) } else { Ok(Palette { colours }) }
+    }
+
+    // Works out the emphasised colours from the 64 base ones
+    // Each emphasis bit (red, green, blue on the 2C02) darkens the other two channels
+    fn with_emphasis(base:&[[u8; 3]]) -> Palette {
+        let mut colours = Vec::with_capacity(512);
+        for emphasis in 0..8 {
+            for rgb in base {
+                let mut rgb = [rgb[0] as f32, rgb[1] as f32, rgb[2] as f32];
+                for channel in 0..3 {
+                    if emphasis & (1 << channel) != 0 {
+                        for (other, value) in rgb.iter_mut().enumerate() {
+                            if other != channel {
+                                *value *= EMPHASIS_ATTENUATION;
+                            }
+                        }
+                    }
+                }
+                colours.push([rgb[0] as u8, rgb[1] as u8, rgb[2] as u8]);
+            }
+        }
+        Palette { colours }
+    }
+
+    // RGB of a pixel from the framebuffer
+    pub fn rgb(&self, pixel:u16) -> [u8; 3] {
+        self.colours[pixel as usize & 0x1FF]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::ntsc()
+    }
+}
+
+// A common NTSC 2C02 palette
+const NTSC: [[u8; 3]; 64] = [
+    [0x54, 0x54, 0x54], [0x00, 0x1E, 0x74], [0x08, 0x10, 0x90], [0x30, 0x00, 0x88],
+    [0x44, 0x00, 0x64], [0x5C, 0x00, 0x30], [0x54, 0x04, 0x00], [0x3C, 0x18, 0x00],
+    [0x20, 0x2A, 0x00], [0x08, 0x3A, 0x00], [0x00, 0x40, 0x00], [0x00, 0x3C, 0x00],
+    [0x00, 0x32, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0x98, 0x96, 0x98], [0x08, 0x4C, 0xC4], [0x30, 0x32, 0xEC], [0x5C, 0x1E, 0xE4],
+    [0x88, 0x14, 0xB0], [0xA0, 0x14, 0x64], [0x98, 0x22, 0x20], [0x78, 0x3C, 0x00],
+    [0x54, 0x5A, 0x00], [0x28, 0x72, 0x00], [0x08, 0x7C, 0x00], [0x00, 0x76, 0x28],
+    [0x00, 0x66, 0x78], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xEC, 0xEE, 0xEC], [0x4C, 0x9A, 0xEC], [0x78, 0x7C, 0xEC], [0xB0, 0x62, 0xEC],
+    [0xE4, 0x54, 0xEC], [0xEC, 0x58, 0xB4], [0xEC, 0x6A, 0x64], [0xD4, 0x88, 0x20],
+    [0xA0, 0xAA, 0x00], [0x74, 0xC4, 0x00], [0x4C, 0xD0, 0x20], [0x38, 0xCC, 0x6C],
+    [0x38, 0xB4, 0xCC], [0x3C, 0x3C, 0x3C], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+    [0xEC, 0xEE, 0xEC], [0xA8, 0xCC, 0xEC], [0xBC, 0xBC, 0xEC], [0xD4, 0xB2, 0xEC],
+    [0xEC, 0xAE, 0xEC], [0xEC, 0xAE, 0xD4], [0xEC, 0xB4, 0xB0], [0xE4, 0xC4, 0x90],
+    [0xCC, 0xD2, 0x78], [0xB4, 0xDE, 0x78], [0xA8, 0xE2, 0x90], [0x98, 0xE2, 0xB4],
+    [0xA0, 0xD6, 0xE4], [0xA0, 0xA2, 0xA0], [0x00, 0x00, 0x00], [0x00, 0x00, 0x00],
+];
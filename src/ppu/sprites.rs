@@ -0,0 +1,176 @@
+// Sprite evaluation, fetching and drawing
+// Each visible line the PPU searches OAM for up to 8 sprites on the next line, copies them into
+// secondary OAM, then fetches their patterns at dots 257 - 320 ready for the next line
+
+use mapper::Mapper;
+use util::check_bit;
+use super::*;
+
+// OAM attribute bit positions
+const ATTR_BEHIND_BG: usize = 5;
+const ATTR_FLIP_X: usize = 6;
+const ATTR_FLIP_Y: usize = 7;
+
+// A sprite's pixel at the current dot
+pub(super) struct SpritePixel {
+    pub(super) index: u8, // palette entry, $10 - $1F
+    pub(super) behind_bg: bool,
+    pub(super) sprite_zero: bool,
+}
+
+impl Ppu {
+    fn sprite_height(&self) -> u16 {
+        if check_bit(self.ctrl, CTRL_SPRITE_SIZE) { 16 } else { 8 }
+    }
+
+    // Dots 1 - 256 of the visible lines
+    // 1 - 64 fill secondary OAM with $FF, 65 - 256 search OAM, reading on odd dots and writing on even ones
+    pub(super) fn evaluate_sprites(&mut self) {
+        let dot = self.dot;
+        match dot {
+            1..=64 if dot & 1 == 0 => self.secondary_oam[(dot as usize / 2) - 1] = 0xFF,
+            65 => {
+                self.eval_n = 0;
+                self.eval_m = 0;
+                self.eval_count = 0;
+                self.eval_done = false;
+                self.eval_sprite_zero = false;
+                self.eval_line = Some(self.scanline);
+            },
+            66..=256 if dot & 1 == 0 => self.evaluate_step(),
+            _ => {},
+        }
+    }
+
+    // One read-write pair of the OAM search
+    fn evaluate_step(&mut self) {
+        if self.eval_done {
+            return;
+        }
+        let val = self.oam[self.eval_n * 4 + self.eval_m];
+        let in_range = self.scanline.wrapping_sub(val as u16) < self.sprite_height();
+        if self.eval_count < 8 {
+            // the Y coordinate is copied whether or not the sprite is in range
+            self.secondary_oam[self.eval_count * 4 + self.eval_m] = val;
+            if self.eval_m == 0 && !in_range {
+                self.next_sprite();
+                return;
+            }
+            if self.eval_m == 0 && self.eval_n == 0 {
+                self.eval_sprite_zero = true;
+            }
+            self.eval_m += 1;
+            if self.eval_m == 4 {
+                self.eval_m = 0;
+                self.eval_count += 1;
+                self.next_sprite();
+            }
+        }
+        else if in_range {
+            // a ninth sprite, though whatever byte is being looked at is taken for its Y coordinate
+            self.status |= 1 << STATUS_SPRITE_OVERFLOW;
+            self.eval_done = true;
+        }
+        else {
+            // hardware bug: m is stepped along with n, so the search drifts off the Y bytes
+            self.eval_m = (self.eval_m + 1) & 0x03;
+            self.next_sprite();
+        }
+    }
+
+    fn next_sprite(&mut self) {
+        self.eval_n += 1;
+        if self.eval_n == 64 {
+            self.eval_done = true;
+        }
+    }
+
+    // Dots 257 - 320, 8 dots for each of the 8 slots
+    // Two garbage nametable reads then the pattern, empty slots fetch tile $FF and draw nothing
+    pub(super) fn fetch_sprite(&mut self, mapper:&mut dyn Mapper) {
+        let dot = self.dot;
+        let slot = (dot as usize - 257) / 8;
+        if dot == 257 {
+            // only a search that started on this line counts, so nothing is found on the pre-render line
+            // (line 0 never has sprites) or when rendering was switched on after dot 65
+            let searched = self.eval_line.take() == Some(self.scanline);
+            self.sprite_count = if searched { self.eval_count } else { 0 };
+            self.sprite_zero_line = searched && self.eval_sprite_zero;
+        }
+        self.oam_addr = 0;
+        match dot % 8 {
+            1 | 3 => { self.read(mapper, 0x2000 | (self.v & 0x0FFF)); },
+            5 => {
+                let addr = self.sprite_pattern_address(slot);
+                let val = self.read(mapper, addr);
+                self.sprite_pattern_lo[slot] = self.sprite_pattern(slot, val);
+            },
+            7 => {
+                let addr = self.sprite_pattern_address(slot) + 8;
+                let val = self.read(mapper, addr);
+                self.sprite_pattern_hi[slot] = self.sprite_pattern(slot, val);
+                self.sprite_attribute[slot] = self.secondary_oam[slot * 4 + 2];
+                self.sprite_x[slot] = self.secondary_oam[slot * 4 + 3];
+            },
+            _ => {},
+        }
+    }
+
+    fn sprite_pattern_address(&self, slot:usize) -> u16 {
+        let sprite = &self.secondary_oam[slot * 4..slot * 4 + 4];
+        let height = self.sprite_height();
+        // the sprite size may have changed since the search, leaving a row outside the sprite
+        let row = self.scanline.wrapping_sub(sprite[0] as u16);
+        let (tile, row) = if slot < self.sprite_count && row < height {
+            (sprite[1], if check_bit(sprite[2], ATTR_FLIP_Y) { height - 1 - row } else { row })
+        }
+        else {
+            (0xFF, 0)
+        };
+        if check_bit(self.ctrl, CTRL_SPRITE_SIZE) {
+            // 8x16 sprites take their table from bit 0 of the tile number, the bottom half is the next tile
+            let table = (tile as u16 & 0x01) << 12;
+            let tile = (tile as u16 & 0xFE) + (row >> 3);
+            table | (tile << 4) | (row & 0x07)
+        }
+        else {
+            let table = if check_bit(self.ctrl, CTRL_SPRITE_TABLE) { 0x1000 } else { 0 };
+            table | ((tile as u16) << 4) | row
+        }
+    }
+
+    // Pattern byte as drawn, flipped if need be and blank for an empty slot
+    fn sprite_pattern(&self, slot:usize, val:u8) -> u8 {
+        if slot >= self.sprite_count {
+            0
+        }
+        else if check_bit(self.secondary_oam[slot * 4 + 2], ATTR_FLIP_X) {
+            val.reverse_bits()
+        }
+        else {
+            val
+        }
+    }
+
+    // The frontmost opaque sprite pixel at x, lower OAM slots win
+    pub(super) fn sprite_pixel(&self, x:usize) -> Option<SpritePixel> {
+        for slot in 0..self.sprite_count {
+            let column = x.wrapping_sub(self.sprite_x[slot] as usize);
+            if column >= 8 {
+                continue;
+            }
+            let bit = 7 - column;
+            let pixel = (((self.sprite_pattern_hi[slot] >> bit) & 1) << 1) | ((self.sprite_pattern_lo[slot] >> bit) & 1);
+            if pixel == 0 {
+                continue;
+            }
+            let attribute = self.sprite_attribute[slot];
+            return Some(SpritePixel {
+                index: 0x10 | ((attribute & 0x03) << 2) | pixel,
+                behind_bg: check_bit(attribute, ATTR_BEHIND_BG),
+                sprite_zero: slot == 0 && self.sprite_zero_line,
+            });
+        }
+        None
+    }
+}
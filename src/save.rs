@@ -0,0 +1,38 @@
+// Battery saves
+// The PRG-RAM of battery backed cartridges is kept in a .sav file next to the ROM
+
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+// foo.nes saves to foo.sav
+pub fn save_path(rom_path:&str) -> PathBuf {
+    Path::new(rom_path).with_extension("sav")
+}
+
+// Fills ram from the save file, returning false if there is no save yet
+// A save of a different size fills as much as both have in common
+pub fn load(path:&Path, ram:&mut [u8]) -> io::Result<bool> {
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(err),
+    };
+    let len = data.len().min(ram.len());
+    ram[..len].copy_from_slice(&data[..len]);
+    Ok(true)
+}
+
+// Writes a temporary file and renames it over the save,
+// so a crash part way through leaves either the old or the new save and never a mix
+pub fn store(path:&Path, ram:&[u8]) -> io::Result<()> {
+    let tmp = path.with_extension("sav.tmp");
+    {
+        let mut file = File::create(&tmp)?;
+        file.write_all(ram)?;
+        file.sync_all()?;
+    }
+    fs::rename(&tmp, path)
+}
@@ -0,0 +1,29 @@
+// Small helpers shared across the emulator
+
+pub fn two_u8_to_u16(a:u8,b:u8) -> u16 {
+    ((a as u16) << 8) | b as u16
+}
+
+// xorshift64 pseudo random number generator
+pub fn xorshift(state:&mut u64) -> u64 {
+    // zero is a fixed point of xorshift, so nudge it away
+    if *state == 0 { *state = 0x9E3779B97F4A7C15; }
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+pub fn check_bit(val:u8, pos:usize) -> bool{
+    let positions:[u8;8] = [
+        0b00000001,
+        0b00000010,
+        0b00000100,
+        0b00001000,
+        0b00010000,
+        0b00100000,
+        0b01000000,
+        0b10000000
+    ];
+    val & positions[pos] == positions[pos]
+}